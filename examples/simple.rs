@@ -1,99 +1,179 @@
-use chip8::{Chip8, Chip8Error};
-use framebrush::{Canvas, RGBu32, WHITE};
-use minifb::{Key, Window, WindowOptions};
-use std::time::{Instant, UNIX_EPOCH};
-
-const DEFAULT_WIDTH: usize = 800;
-const DEFAULT_HEIGHT: usize = 600;
-
-fn main() -> Result<(), Chip8Error> {
-    let mut buf = vec![0; DEFAULT_WIDTH * DEFAULT_HEIGHT];
-    let mut chip8 = Chip8::new(Default::default());
-
-    let file_path = {
-        let mut args = std::env::args();
-        let program = args.next().unwrap();
-        if let Some(p) = args.next() {
-            p
-        } else {
-            eprintln!("Usage: {program} <rom path>");
-            return Ok(());
-        }
-    };
-    let program = std::fs::read(&file_path).expect("file not found");
-    chip8.set_program(&program);
-    let mut window = Window::new(
-        "chip8",
-        DEFAULT_WIDTH,
-        DEFAULT_HEIGHT,
-        WindowOptions {
-            resize: true,
-            ..Default::default()
-        },
-    )
-    .unwrap();
-
-    let keys = [
-        (Key::Key1, 0x1u8),
-        (Key::Key2, 0x2),
-        (Key::Key3, 0x3),
-        (Key::Key4, 0xC),
-        (Key::Q, 0x4),
-        (Key::W, 0x5),
-        (Key::E, 0x6),
-        (Key::R, 0xD),
-        (Key::A, 0x7),
-        (Key::S, 0x8),
-        (Key::D, 0x9),
-        (Key::F, 0xE),
-        (Key::Z, 0xA),
-        (Key::X, 0x0),
-        (Key::C, 0xB),
-        (Key::V, 0xF),
-    ];
-
-    let mut last_frame = Instant::now();
-    window.set_target_fps(144);
-    while window.is_open() {
-        let delta = {
-            let now = Instant::now();
-            let res = now.duration_since(last_frame).as_micros();
-            last_frame = now;
-            res
-        };
-        let (width, height) = window.get_size();
-        buf.resize(width * height, 0);
-        for (key, code) in keys {
-            if window.is_key_down(key) {
-                chip8.press(code)
-            } else {
-                chip8.release(code)
-            }
-        }
-
-        if chip8.should_play_sound() {
-            // TODO: Play sound here
-        }
-
-        chip8.update(delta, || {
-            (UNIX_EPOCH.elapsed().unwrap().as_micros() % 255) as u8
-        })?;
-
-        // Begin drawing
-        let mut canvas = Canvas::new(&mut buf, (width, height), (Chip8::WIDTH, Chip8::HEIGHT));
-        canvas.fill(0);
-        for y in 0..Chip8::HEIGHT {
-            for x in 0..Chip8::WIDTH {
-                let color = if chip8.framebuffer[y * Chip8::WIDTH + x] {
-                    WHITE
-                } else {
-                    RGBu32::Rgb(0, 0, 0)
-                };
-                canvas.rect(x as i32, y as i32, 1, 1, &color);
-            }
-        }
-        // End drawing
-        window.update_with_buffer(&buf, width, height).unwrap();
-    }
-    Ok(())
-}
+use chip8::{Chip8, Chip8Error, CosmacVip};
+use framebrush::{Canvas, RGBu32, WHITE};
+use minifb::{Key, Window, WindowOptions};
+use std::time::{Instant, UNIX_EPOCH};
+
+const DEFAULT_WIDTH: usize = 800;
+const DEFAULT_HEIGHT: usize = 600;
+
+/// Either the interpreter or the whole COSMAC VIP running the original one, they only differ in `update`
+enum Machine {
+    Chip8(Box<Chip8>),
+    Vip(Box<CosmacVip>),
+}
+
+impl Machine {
+    fn update(&mut self, delta: u128) -> Result<(), Chip8Error> {
+        match self {
+            Machine::Chip8(chip8) => chip8.update(delta, || {
+                (UNIX_EPOCH.elapsed().unwrap().as_micros() % 255) as u8
+            }),
+            // The VIP's interpreter gets its random numbers from the machine
+            Machine::Vip(vip) => {
+                vip.update(delta);
+                Ok(())
+            }
+        }
+    }
+
+    fn press(&mut self, key: u8) {
+        match self {
+            Machine::Chip8(chip8) => chip8.press(key),
+            Machine::Vip(vip) => vip.press(key),
+        }
+    }
+
+    fn release(&mut self, key: u8) {
+        match self {
+            Machine::Chip8(chip8) => chip8.release(key),
+            Machine::Vip(vip) => vip.release(key),
+        }
+    }
+
+    fn should_play_sound(&self) -> bool {
+        match self {
+            Machine::Chip8(chip8) => chip8.should_play_sound(),
+            Machine::Vip(vip) => vip.should_play_sound(),
+        }
+    }
+
+    fn has_exited(&self) -> bool {
+        match self {
+            Machine::Chip8(chip8) => chip8.has_exited(),
+            Machine::Vip(_) => false,
+        }
+    }
+
+    fn size(&self) -> (usize, usize) {
+        match self {
+            Machine::Chip8(chip8) => (chip8.width(), chip8.height()),
+            Machine::Vip(vip) => (vip.width(), vip.height()),
+        }
+    }
+
+    fn framebuffer(&self) -> &[u8] {
+        match self {
+            Machine::Chip8(chip8) => &chip8.framebuffer,
+            Machine::Vip(vip) => &vip.framebuffer,
+        }
+    }
+}
+
+fn main() -> Result<(), Chip8Error> {
+    let mut buf = vec![0; DEFAULT_WIDTH * DEFAULT_HEIGHT];
+
+    let mut args = std::env::args();
+    let name = args.next().unwrap();
+    let Some(file_path) = args.next() else {
+        eprintln!("Usage: {name} <rom path> [<VIP monitor ROM path> <VIP interpreter path>]");
+        return Ok(());
+    };
+    let program = std::fs::read(&file_path).expect("file not found");
+    // With the two 512-byte VIP images the program runs on the emulated COSMAC VIP instead
+    let mut machine = if let (Some(monitor), Some(interpreter)) = (args.next(), args.next()) {
+        let monitor = std::fs::read(monitor).expect("file not found");
+        let interpreter = std::fs::read(interpreter).expect("file not found");
+        let mut vip = CosmacVip::new(
+            monitor
+                .as_slice()
+                .try_into()
+                .expect("monitor ROM must be 512 bytes"),
+            interpreter
+                .as_slice()
+                .try_into()
+                .expect("interpreter must be 512 bytes"),
+        );
+        vip.set_program(&program)?;
+        Machine::Vip(Box::new(vip))
+    } else {
+        let mut chip8 = Chip8::new(Default::default());
+        chip8.set_program(&program)?;
+        Machine::Chip8(Box::new(chip8))
+    };
+    let mut window = Window::new(
+        "chip8",
+        DEFAULT_WIDTH,
+        DEFAULT_HEIGHT,
+        WindowOptions {
+            resize: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    let keys = [
+        (Key::Key1, 0x1u8),
+        (Key::Key2, 0x2),
+        (Key::Key3, 0x3),
+        (Key::Key4, 0xC),
+        (Key::Q, 0x4),
+        (Key::W, 0x5),
+        (Key::E, 0x6),
+        (Key::R, 0xD),
+        (Key::A, 0x7),
+        (Key::S, 0x8),
+        (Key::D, 0x9),
+        (Key::F, 0xE),
+        (Key::Z, 0xA),
+        (Key::X, 0x0),
+        (Key::C, 0xB),
+        (Key::V, 0xF),
+    ];
+
+    let mut last_frame = Instant::now();
+    window.set_target_fps(144);
+    while window.is_open() {
+        let delta = {
+            let now = Instant::now();
+            let res = now.duration_since(last_frame).as_micros();
+            last_frame = now;
+            res
+        };
+        let (width, height) = window.get_size();
+        buf.resize(width * height, 0);
+        for (key, code) in keys {
+            if window.is_key_down(key) {
+                machine.press(code)
+            } else {
+                machine.release(code)
+            }
+        }
+
+        if machine.should_play_sound() {
+            // TODO: Play sound here
+        }
+
+        machine.update(delta)?;
+        if machine.has_exited() {
+            break;
+        }
+
+        // Begin drawing
+        let (chip8_width, chip8_height) = machine.size();
+        let mut canvas = Canvas::new(&mut buf, (width, height), (chip8_width, chip8_height));
+        canvas.fill(0);
+        for y in 0..chip8_height {
+            for x in 0..chip8_width {
+                let color = if machine.framebuffer()[y * chip8_width + x] != 0 {
+                    WHITE
+                } else {
+                    RGBu32::Rgb(0, 0, 0)
+                };
+                canvas.rect(x as i32, y as i32, 1, 1, &color);
+            }
+        }
+        // End drawing
+        window.update_with_buffer(&buf, width, height).unwrap();
+    }
+    Ok(())
+}
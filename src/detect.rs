@@ -0,0 +1,294 @@
+//! Guessing the platform a ROM was written for from the instructions it uses
+
+use crate::{Chip8, Chip8Config};
+
+/// A platform `Chip8Config::detect` thinks a ROM may have been written for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub config: Chip8Config,
+    /// From 0 to 100, how likely it is that the ROM was written for this platform
+    pub confidence: u8,
+}
+
+impl Candidate {
+    /// How many platforms `Chip8Config::detect` ranks, MegaChip needs the `alloc` feature
+    #[cfg(feature = "alloc")]
+    pub const COUNT: usize = 10;
+    #[cfg(not(feature = "alloc"))]
+    pub const COUNT: usize = 9;
+}
+
+/// What a ROM uses, found by following the code from its first instruction.
+/// Jumps and calls can land on odd addresses, so code after an odd number of data bytes is found too.
+#[derive(Default)]
+struct Evidence {
+    hires_prologue: bool,
+    megachip_on: bool,
+    superchip: usize,
+    xo_chip: usize,
+    chip8x: usize,
+    chip8e: usize,
+    megachip: usize,
+    machine_code: usize,
+    /// Jump and call targets inside and outside of the ROM, tells whether it was written for this program start
+    targets_inside: usize,
+    targets_outside: usize,
+}
+
+impl Evidence {
+    /// Most branches that wait to be followed, any more are dropped
+    const PENDING: usize = 64;
+
+    fn scan(rom: &[u8], program_start: usize) -> Self {
+        let mut evidence = Self {
+            hires_prologue: rom.starts_with(&[0x12, 0x60]),
+            ..Self::default()
+        };
+        // Nothing past the largest memory can ever run
+        let rom = &rom[..rom.len().min(Chip8::MAX_MEMORY_SIZE)];
+        let opcode = |offset: usize| {
+            (offset + 1 < rom.len()).then(|| u16::from_be_bytes([rom[offset], rom[offset + 1]]))
+        };
+        // F000 NNNN is four bytes long, skips have to jump over all of it
+        let size = |offset: usize| if opcode(offset) == Some(0xF000) { 4 } else { 2 };
+        // One bit per byte of the largest ROM that fits in memory
+        let mut visited = [0u32; Chip8::MAX_MEMORY_SIZE / 32];
+        let mut pending = Pending {
+            offsets: [0; Self::PENDING],
+            len: 1,
+        };
+        while let Some(mut offset) = pending.pop() {
+            while let Some(instruction) = opcode(offset) {
+                let (word, bit) = (offset / 32, 1 << (offset % 32));
+                if visited[word] & bit != 0 {
+                    break;
+                }
+                visited[word] |= bit;
+                evidence.count(instruction);
+
+                let next = offset + size(offset);
+                let [high, low] = instruction.to_be_bytes();
+                match (high >> 4, low) {
+                    _ if matches!(instruction, 0x00EE | 0x00FD | 0x00ED) => break,
+                    (0x1, _) => {
+                        if let Some(target) = evidence.target(instruction, program_start, rom.len())
+                        {
+                            pending.push(target);
+                        }
+                        break;
+                    }
+                    (0x2, _) => {
+                        if let Some(target) = evidence.target(instruction, program_start, rom.len())
+                        {
+                            pending.push(target);
+                        }
+                    }
+                    // Skips, including CHIP-8X's second keypad and CHIP-8E's 0188
+                    (0x3 | 0x4 | 0x5 | 0x9, _) | (0xE, 0x9E | 0xA1 | 0xF2 | 0xF5) => {
+                        pending.push(next + size(next))
+                    }
+                    _ if instruction == 0x0188 => pending.push(next + size(next)),
+                    // BNNN jumps somewhere that can't be known here, it is usually followed by a table of jumps
+                    _ => (),
+                }
+                offset = next;
+            }
+        }
+        evidence
+    }
+
+    /// Counts a jump or call to the address in the low 12 bits of `instruction`, returns its offset in the ROM
+    fn target(&mut self, instruction: u16, program_start: usize, rom_len: usize) -> Option<usize> {
+        let address = (instruction & 0x0FFF) as usize;
+        if (program_start..program_start + rom_len).contains(&address) {
+            self.targets_inside += 1;
+            Some(address - program_start)
+        } else {
+            self.targets_outside += 1;
+            None
+        }
+    }
+
+    /// Counts the platforms `instruction` is an instruction of
+    fn count(&mut self, instruction: u16) {
+        let [high, low] = instruction.to_be_bytes();
+        let (nibble_0, nibble_1) = (high >> 4, high & 0xF);
+        let (nibble_2, nibble_3) = (low >> 4, low & 0xF);
+        match (nibble_0, nibble_1, nibble_2, nibble_3) {
+            // 0010, 0011, 00BN, 0700, 080N
+            _ if instruction == 0x0011 => {
+                self.megachip_on = true;
+                self.megachip += 1
+            }
+            (0x0, 0x0, 0x1, 0x0)
+            | (0x0, 0x0, 0xB, _)
+            | (0x0, 0x7, 0x0, 0x0)
+            | (0x0, 0x8, 0x0, _) => self.megachip += 1,
+            // 00CN, 00FB-00FF, DXY0, FX30, FX75, FX85
+            (0x0, 0x0, 0xC, _) | (0x0, 0x0, 0xF, 0xB..=0xF) | (0xD, _, _, 0x0) => {
+                self.superchip += 1
+            }
+            (0xF, _, 0x3, 0x0) | (0xF, _, 0x7 | 0x8, 0x5) => self.superchip += 1,
+            // 00DN, 5XY2, 5XY3, F000, FN01, F002, FX3A
+            (0x0, 0x0, 0xD, _) | (0x5, _, _, 0x2 | 0x3) => self.xo_chip += 1,
+            (0xF, 0x0, 0x0, 0x0 | 0x2) | (0xF, 0x0..=0x3, 0x0, 0x1) | (0xF, _, 0x3, 0xA) => {
+                self.xo_chip += 1
+            }
+            // 5XY1 is an instruction of both CHIP-8X and CHIP-8E
+            (0x5, _, _, 0x1) => {
+                self.chip8x += 1;
+                self.chip8e += 1;
+            }
+            // 02A0, EXF2, EXF5
+            (0x0, 0x2, 0xA, 0x0) | (0xE, _, 0xF, 0x2 | 0x5) => self.chip8x += 1,
+            // 00ED, 00F2, 0151, 0188, FX03, FX1B, FX4F, FXE3, FXE7
+            _ if matches!(instruction, 0x00ED | 0x00F2 | 0x0151 | 0x0188) => self.chip8e += 1,
+            (0xF, _, 0x0, 0x3)
+            | (0xF, _, 0x1, 0xB)
+            | (0xF, _, 0x4, 0xF)
+            | (0xF, _, 0xE, 0x3 | 0x7) => self.chip8e += 1,
+            (0x0, 0x0, 0xE, 0x0 | 0xE) | (0x0, 0x0, 0x0, 0x0) => (),
+            (0x0, _, _, _) => self.machine_code += 1,
+            _ => (),
+        }
+    }
+
+    /// From 0 to 100, how many of the jumps and calls land inside the ROM. ROMs without any could start anywhere.
+    fn layout(&self) -> u8 {
+        (self.targets_inside * 100)
+            .checked_div(self.targets_inside + self.targets_outside)
+            .map_or(100, |layout| layout as u8)
+    }
+}
+
+/// Offsets in the ROM `Evidence::scan` still has to follow
+struct Pending {
+    offsets: [usize; Evidence::PENDING],
+    len: usize,
+}
+
+impl Pending {
+    /// Dropped when too many are waiting already
+    fn push(&mut self, offset: usize) {
+        if self.len < self.offsets.len() {
+            self.offsets[self.len] = offset;
+            self.len += 1;
+        }
+    }
+    fn pop(&mut self) -> Option<usize> {
+        self.len = self.len.checked_sub(1)?;
+        Some(self.offsets[self.len])
+    }
+}
+
+/// Confidence from how often an instruction only some platforms have shows up, data can look like one now and then
+fn confidence_from(count: usize) -> u8 {
+    match count {
+        0 => 0,
+        1 => 30,
+        2..=3 => 55,
+        _ => 80,
+    }
+}
+
+impl Chip8Config {
+    /// Ranks the platforms `rom` may have been written for, most likely first.
+    /// The DREAM 6800 runs the same instructions as the COSMAC VIP, so it always ranks just below it.
+    pub fn detect(rom: &[u8]) -> [Candidate; Candidate::COUNT] {
+        let evidence = Evidence::scan(rom, Self::PROGRAM_START);
+        let superchip = confidence_from(evidence.superchip);
+        let xo_chip = confidence_from(evidence.xo_chip);
+        let machine_code = confidence_from(evidence.machine_code);
+        let chip8x = confidence_from(evidence.chip8x);
+        let chip8e = confidence_from(evidence.chip8e);
+        let megachip = confidence_from(evidence.megachip);
+        let extensions = superchip / 2 + xo_chip / 2 + chip8x / 2 + chip8e / 2 + megachip / 2;
+        let cosmac_vip = (40 + machine_code / 2).saturating_sub(extensions);
+
+        // The ETI-660 and CHIP-8X load programs elsewhere, so the jumps only make sense from there
+        let eti_660 = Evidence::scan(rom, Self::eti_660().program_start);
+        let eti_660_confidence = (25 + confidence_from(eti_660.machine_code) / 2).saturating_sub(
+            confidence_from(eti_660.superchip) / 2
+                + confidence_from(eti_660.xo_chip) / 2
+                + confidence_from(eti_660.chip8x) / 2
+                + confidence_from(eti_660.chip8e) / 2,
+        );
+        let chip8x_evidence = Evidence::scan(rom, Self::chip8x().program_start);
+
+        let mut candidates = [
+            (
+                Self::modern(),
+                50u8.saturating_sub(extensions + machine_code / 2),
+                evidence.layout(),
+            ),
+            (Self::cosmac_vip(), cosmac_vip, evidence.layout()),
+            (
+                Self::chip8_hires(),
+                if evidence.hires_prologue { 95 } else { 0 },
+                evidence.layout(),
+            ),
+            (
+                Self::schip_1_1(),
+                (10 + superchip).saturating_sub(xo_chip / 2 + megachip / 2),
+                evidence.layout(),
+            ),
+            (
+                Self::xo_chip(),
+                (5 + superchip / 2 + xo_chip).min(100),
+                evidence.layout(),
+            ),
+            (
+                Self::chip8x(),
+                (10 + confidence_from(chip8x_evidence.chip8x)).saturating_sub(
+                    confidence_from(chip8x_evidence.superchip) / 2
+                        + confidence_from(chip8x_evidence.xo_chip) / 2
+                        + confidence_from(chip8x_evidence.chip8e) / 2,
+                ),
+                chip8x_evidence.layout(),
+            ),
+            (
+                Self::chip8e(),
+                (10 + chip8e).saturating_sub(superchip / 2 + xo_chip / 2 + chip8x / 2),
+                evidence.layout(),
+            ),
+            (Self::eti_660(), eti_660_confidence, eti_660.layout()),
+            (
+                Self::dream_6800(),
+                cosmac_vip.saturating_sub(10),
+                evidence.layout(),
+            ),
+            #[cfg(feature = "alloc")]
+            (
+                Self::megachip(),
+                if evidence.megachip_on {
+                    95
+                } else {
+                    (5 + superchip / 2 + megachip).min(100)
+                },
+                evidence.layout(),
+            ),
+        ]
+        .map(|(config, confidence, layout)| {
+            let confidence = (confidence as usize * layout as usize / 100) as u8;
+            let too_large = rom.len() > config.memory_size.saturating_sub(config.program_start);
+            let confidence = if too_large {
+                0
+            } else if rom.len() > Chip8::MEMORY_SIZE - Self::PROGRAM_START {
+                // Only the platforms with more memory can hold it at all
+                100
+            } else {
+                confidence
+            };
+            Candidate { config, confidence }
+        });
+        // Insertion sort, keeps the order above for equal confidences
+        for i in 1..candidates.len() {
+            let mut j = i;
+            while j > 0 && candidates[j - 1].confidence < candidates[j].confidence {
+                candidates.swap(j - 1, j);
+                j -= 1;
+            }
+        }
+        candidates
+    }
+}
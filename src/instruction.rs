@@ -0,0 +1,553 @@
+//! Decoding and encoding of instructions, shared by `Chip8` and anything else that needs to understand programs
+
+use crate::Variant;
+
+/// An opcode that isn't an instruction of the variant it was decoded for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeError {
+    pub opcode: u16,
+}
+
+impl core::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Illegal instruction: {:0>4X}", self.opcode)
+    }
+}
+
+impl core::error::Error for DecodeError {}
+
+/// A single instruction, `x` and `y` are register indices.
+/// The names and the `Display` impl follow Octo's syntax where there is one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    /// 00E0
+    Clear,
+    /// 00EE
+    Return,
+    /// 0NNN, runs a machine code routine
+    MachineCode { address: u16 },
+    /// 1NNN
+    Jump { address: u16 },
+    /// 2NNN
+    Call { address: u16 },
+    /// 3XNN, skips the next instruction if vx == NN
+    SkipIfEqual { x: u8, value: u8 },
+    /// 4XNN, skips the next instruction if vx != NN
+    SkipIfNotEqual { x: u8, value: u8 },
+    /// 5XY0, skips the next instruction if vx == vy
+    SkipIfRegistersEqual { x: u8, y: u8 },
+    /// 6XNN
+    Load { x: u8, value: u8 },
+    /// 7XNN, doesn't touch vf
+    Add { x: u8, value: u8 },
+    /// 8XY0
+    Move { x: u8, y: u8 },
+    /// 8XY1
+    Or { x: u8, y: u8 },
+    /// 8XY2
+    And { x: u8, y: u8 },
+    /// 8XY3
+    Xor { x: u8, y: u8 },
+    /// 8XY4
+    AddRegisters { x: u8, y: u8 },
+    /// 8XY5
+    Subtract { x: u8, y: u8 },
+    /// 8XY6
+    ShiftRight { x: u8, y: u8 },
+    /// 8XY7
+    SubtractReverse { x: u8, y: u8 },
+    /// 8XYE
+    ShiftLeft { x: u8, y: u8 },
+    /// 9XY0, skips the next instruction if vx != vy
+    SkipIfRegistersNotEqual { x: u8, y: u8 },
+    /// ANNN
+    LoadIndex { address: u16 },
+    /// BNNN
+    JumpOffset { address: u16 },
+    /// CXNN
+    Random { x: u8, mask: u8 },
+    /// DXYN
+    Draw { x: u8, y: u8, n: u8 },
+    /// EX9E, skips the next instruction if key vx is pressed
+    SkipIfKey { x: u8 },
+    /// EXA1, skips the next instruction if key vx isn't pressed
+    SkipIfNotKey { x: u8 },
+    /// FX07
+    LoadDelay { x: u8 },
+    /// FX0A
+    WaitKey { x: u8 },
+    /// FX15
+    SetDelay { x: u8 },
+    /// FX18
+    SetSound { x: u8 },
+    /// FX1E
+    AddIndex { x: u8 },
+    /// FX29
+    LoadFont { x: u8 },
+    /// FX33
+    Bcd { x: u8 },
+    /// FX55
+    SaveRegisters { x: u8 },
+    /// FX65
+    LoadRegisters { x: u8 },
+
+    /// 0230, CHIP-8 HIRES
+    HiresClear,
+
+    /// 00CN, SUPER-CHIP
+    ScrollDown { n: u8 },
+    /// 00FB, SUPER-CHIP
+    ScrollRight,
+    /// 00FC, SUPER-CHIP
+    ScrollLeft,
+    /// 00FD, SUPER-CHIP
+    Exit,
+    /// 00FE, SUPER-CHIP
+    Lores,
+    /// 00FF, SUPER-CHIP
+    Hires,
+    /// FX30, SUPER-CHIP
+    LoadBigFont { x: u8 },
+    /// FX75, SUPER-CHIP
+    SaveFlags { x: u8 },
+    /// FX85, SUPER-CHIP
+    LoadFlags { x: u8 },
+
+    /// 00DN, XO-CHIP
+    ScrollUp { n: u8 },
+    /// 5XY2, XO-CHIP and CHIP-8E
+    SaveRange { x: u8, y: u8 },
+    /// 5XY3, XO-CHIP and CHIP-8E
+    LoadRange { x: u8, y: u8 },
+    /// F000, XO-CHIP. The address is in the two bytes after the opcode.
+    LongIndex,
+    /// FN01, XO-CHIP
+    Plane { n: u8 },
+    /// F002, XO-CHIP
+    Audio,
+    /// FX3A, XO-CHIP
+    Pitch { x: u8 },
+
+    /// 02A0, CHIP-8X
+    StepBackground,
+    /// 5XY1, CHIP-8X
+    AddNibbles { x: u8, y: u8 },
+    /// BXY0, CHIP-8X
+    ColorZones { x: u8, y: u8 },
+    /// BXYN, CHIP-8X
+    ColorRows { x: u8, y: u8, n: u8 },
+    /// EXF2, CHIP-8X, skips the next instruction if key vx on the second keypad is pressed
+    SkipIfKey2 { x: u8 },
+    /// EXF5, CHIP-8X, skips the next instruction if key vx on the second keypad isn't pressed
+    SkipIfNotKey2 { x: u8 },
+
+    /// 00ED, CHIP-8E
+    Stop,
+    /// 00F2, CHIP-8E
+    Nop,
+    /// 0151, CHIP-8E
+    WaitDelay,
+    /// 0188, CHIP-8E
+    SkipNext,
+    /// 5XY1, CHIP-8E, skips the next instruction if vx > vy
+    SkipIfGreater { x: u8, y: u8 },
+    /// BBNN, CHIP-8E
+    JumpBack { offset: u8 },
+    /// BFNN, CHIP-8E
+    JumpForward { offset: u8 },
+    /// FX03, CHIP-8E
+    Output { x: u8 },
+    /// FX1B, CHIP-8E
+    SkipBytes { x: u8 },
+    /// FX4F, CHIP-8E
+    Delay { x: u8 },
+    /// FXE3, CHIP-8E
+    WaitInput { x: u8 },
+    /// FXE7, CHIP-8E
+    Input { x: u8 },
+
+    /// MegaChip's `0NNN` instructions
+    #[cfg(feature = "alloc")]
+    MegaChip(MegaChipInstruction),
+}
+
+/// The instructions MegaChip adds in the `0NNN` space
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MegaChipInstruction {
+    /// 0010
+    MegaOff,
+    /// 0011
+    MegaOn,
+    /// 00BN
+    ScrollUp { n: u8 },
+    /// 01NN, the low 16 bits of the address are in the two bytes after the opcode.
+    /// Only addresses below `Chip8Config::memory_size` can be loaded.
+    LongIndex { high: u8 },
+    /// 02NN
+    LoadPalette { count: u8 },
+    /// 03NN
+    SpriteWidth { width: u8 },
+    /// 04NN
+    SpriteHeight { height: u8 },
+    /// 05NN
+    ScreenAlpha { alpha: u8 },
+    /// 060N
+    PlaySound { n: u8 },
+    /// 0700
+    StopSound,
+    /// 080N
+    BlendMode { mode: u8 },
+    /// 09NN
+    CollisionColor { color: u8 },
+}
+
+#[cfg(feature = "alloc")]
+impl MegaChipInstruction {
+    fn decode(opcode: u16) -> Option<Self> {
+        let [high, low] = opcode.to_be_bytes();
+        let n = low & 0xF;
+        Some(match (high, low >> 4) {
+            (0x00, 0x1) if n == 0x0 => Self::MegaOff,
+            (0x00, 0x1) if n == 0x1 => Self::MegaOn,
+            (0x00, 0xB) => Self::ScrollUp { n },
+            (0x01, _) => Self::LongIndex { high: low },
+            (0x02, _) => Self::LoadPalette { count: low },
+            (0x03, _) => Self::SpriteWidth { width: low },
+            (0x04, _) => Self::SpriteHeight { height: low },
+            (0x05, _) => Self::ScreenAlpha { alpha: low },
+            (0x06, 0x0) => Self::PlaySound { n },
+            (0x07, 0x0) if n == 0x0 => Self::StopSound,
+            (0x08, 0x0) => Self::BlendMode { mode: n },
+            (0x09, _) => Self::CollisionColor { color: low },
+            _ => return None,
+        })
+    }
+
+    fn encode(self) -> u16 {
+        match self {
+            Self::MegaOff => 0x0010,
+            Self::MegaOn => 0x0011,
+            Self::ScrollUp { n } => 0x00B0 | (n & 0xF) as u16,
+            Self::LongIndex { high } => 0x0100 | high as u16,
+            Self::LoadPalette { count } => 0x0200 | count as u16,
+            Self::SpriteWidth { width } => 0x0300 | width as u16,
+            Self::SpriteHeight { height } => 0x0400 | height as u16,
+            Self::ScreenAlpha { alpha } => 0x0500 | alpha as u16,
+            Self::PlaySound { n } => 0x0600 | (n & 0xF) as u16,
+            Self::StopSound => 0x0700,
+            Self::BlendMode { mode } => 0x0800 | (mode & 0xF) as u16,
+            Self::CollisionColor { color } => 0x0900 | color as u16,
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl core::fmt::Display for MegaChipInstruction {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::MegaOff => write!(f, "megaoff"),
+            Self::MegaOn => write!(f, "megaon"),
+            Self::ScrollUp { n } => write!(f, "scroll-up {}", n),
+            Self::LongIndex { high } => write!(f, "i := long 0x{:0>2X}....", high),
+            Self::LoadPalette { count } => write!(f, "palette {}", count),
+            Self::SpriteWidth { width } => write!(f, "sprite-width {}", width),
+            Self::SpriteHeight { height } => write!(f, "sprite-height {}", height),
+            Self::ScreenAlpha { alpha } => write!(f, "alpha 0x{:0>2X}", alpha),
+            Self::PlaySound { n } => write!(f, "digisound {}", n),
+            Self::StopSound => write!(f, "stop-digisound"),
+            Self::BlendMode { mode } => write!(f, "blend {}", mode),
+            Self::CollisionColor { color } => write!(f, "collision-color {}", color),
+        }
+    }
+}
+
+impl Instruction {
+    /// Decodes `opcode` as XO-CHIP, which includes everything CHIP-8 and SUPER-CHIP have
+    pub fn decode(opcode: u16) -> Result<Self, DecodeError> {
+        Self::decode_for(opcode, Variant::XoChip)
+    }
+
+    /// Decodes `opcode` the way `variant` reads it.
+    /// 5XYN and 9XYN with an N that isn't an instruction of `variant` are errors, even though the COSMAC VIP
+    /// interpreter ignored N.
+    pub fn decode_for(opcode: u16, variant: Variant) -> Result<Self, DecodeError> {
+        let [high, low] = opcode.to_be_bytes();
+        let (nibble_0, x) = (high >> 4, high & 0xF);
+        let (y, n) = (low >> 4, low & 0xF);
+        let address = opcode & 0x0FFF;
+        let error = DecodeError { opcode };
+        let instruction = match nibble_0 {
+            0x0 => {
+                #[cfg(feature = "alloc")]
+                if variant == Variant::MegaChip {
+                    if let Some(instruction) = MegaChipInstruction::decode(opcode) {
+                        return Ok(Self::MegaChip(instruction));
+                    }
+                }
+                match opcode {
+                    0x00E0 => Self::Clear,
+                    0x00EE => Self::Return,
+                    0x0230 if variant == Variant::Chip8Hires => Self::HiresClear,
+                    0x02A0 if variant.chip8x() => Self::StepBackground,
+                    0x00ED if variant.chip8e() => Self::Stop,
+                    0x00F2 if variant.chip8e() => Self::Nop,
+                    0x0151 if variant.chip8e() => Self::WaitDelay,
+                    0x0188 if variant.chip8e() => Self::SkipNext,
+                    0x00C0..=0x00CF if variant.superchip() => Self::ScrollDown { n },
+                    0x00D0..=0x00DF if variant.xo_chip() => Self::ScrollUp { n },
+                    0x00FB if variant.superchip() => Self::ScrollRight,
+                    0x00FC if variant.superchip() => Self::ScrollLeft,
+                    0x00FD if variant.superchip() => Self::Exit,
+                    0x00FE if variant.superchip() => Self::Lores,
+                    0x00FF if variant.superchip() => Self::Hires,
+                    _ => Self::MachineCode { address },
+                }
+            }
+            0x1 => Self::Jump { address },
+            0x2 => Self::Call { address },
+            0x3 => Self::SkipIfEqual { x, value: low },
+            0x4 => Self::SkipIfNotEqual { x, value: low },
+            0x5 => match n {
+                0x1 if variant.chip8e() => Self::SkipIfGreater { x, y },
+                0x1 if variant.chip8x() => Self::AddNibbles { x, y },
+                0x2 if variant.xo_chip() || variant.chip8e() => Self::SaveRange { x, y },
+                0x3 if variant.xo_chip() || variant.chip8e() => Self::LoadRange { x, y },
+                0x0 => Self::SkipIfRegistersEqual { x, y },
+                _ => return Err(error),
+            },
+            0x6 => Self::Load { x, value: low },
+            0x7 => Self::Add { x, value: low },
+            0x8 => match n {
+                0x0 => Self::Move { x, y },
+                0x1 => Self::Or { x, y },
+                0x2 => Self::And { x, y },
+                0x3 => Self::Xor { x, y },
+                0x4 => Self::AddRegisters { x, y },
+                0x5 => Self::Subtract { x, y },
+                0x6 => Self::ShiftRight { x, y },
+                0x7 => Self::SubtractReverse { x, y },
+                0xE => Self::ShiftLeft { x, y },
+                _ => return Err(error),
+            },
+            0x9 if n == 0x0 => Self::SkipIfRegistersNotEqual { x, y },
+            0x9 => return Err(error),
+            0xA => Self::LoadIndex { address },
+            0xB if variant.chip8x() && n == 0x0 => Self::ColorZones { x, y },
+            0xB if variant.chip8x() => Self::ColorRows { x, y, n },
+            0xB if variant.chip8e() && x == 0xB => Self::JumpBack { offset: low },
+            0xB if variant.chip8e() && x == 0xF => Self::JumpForward { offset: low },
+            0xB => Self::JumpOffset { address },
+            0xC => Self::Random { x, mask: low },
+            0xD => Self::Draw { x, y, n },
+            0xE => match low {
+                0x9E => Self::SkipIfKey { x },
+                0xA1 => Self::SkipIfNotKey { x },
+                0xF2 if variant.chip8x() => Self::SkipIfKey2 { x },
+                0xF5 if variant.chip8x() => Self::SkipIfNotKey2 { x },
+                _ => return Err(error),
+            },
+            _ => match low {
+                0x00 if variant.xo_chip() && x == 0x0 => Self::LongIndex,
+                0x01 if variant.xo_chip() => Self::Plane { n: x },
+                0x02 if variant.xo_chip() && x == 0x0 => Self::Audio,
+                0x03 if variant.chip8e() => Self::Output { x },
+                0x07 => Self::LoadDelay { x },
+                0x0A => Self::WaitKey { x },
+                0x15 => Self::SetDelay { x },
+                0x18 => Self::SetSound { x },
+                0x1B if variant.chip8e() => Self::SkipBytes { x },
+                0x1E => Self::AddIndex { x },
+                0x29 => Self::LoadFont { x },
+                0x30 if variant.superchip() => Self::LoadBigFont { x },
+                0x33 => Self::Bcd { x },
+                0x3A if variant.xo_chip() => Self::Pitch { x },
+                0x4F if variant.chip8e() => Self::Delay { x },
+                0x55 => Self::SaveRegisters { x },
+                0x65 => Self::LoadRegisters { x },
+                0x75 if variant.superchip() => Self::SaveFlags { x },
+                0x85 if variant.superchip() => Self::LoadFlags { x },
+                0xE3 if variant.chip8e() => Self::WaitInput { x },
+                0xE7 if variant.chip8e() => Self::Input { x },
+                _ => return Err(error),
+            },
+        };
+        Ok(instruction)
+    }
+
+    /// The opcode of the instruction, register indices and addresses are truncated to fit
+    pub fn encode(&self) -> u16 {
+        let xy = |nibble_0: u16, x: u8, y: u8, n: u16| {
+            (nibble_0 << 12) | (((x & 0xF) as u16) << 8) | (((y & 0xF) as u16) << 4) | n
+        };
+        let x_low = |nibble_0: u16, x: u8, low: u8| {
+            (nibble_0 << 12) | (((x & 0xF) as u16) << 8) | low as u16
+        };
+        let nnn = |nibble_0: u16, address: u16| (nibble_0 << 12) | (address & 0x0FFF);
+        match *self {
+            Self::Clear => 0x00E0,
+            Self::Return => 0x00EE,
+            Self::MachineCode { address } => nnn(0x0, address),
+            Self::Jump { address } => nnn(0x1, address),
+            Self::Call { address } => nnn(0x2, address),
+            Self::SkipIfEqual { x, value } => x_low(0x3, x, value),
+            Self::SkipIfNotEqual { x, value } => x_low(0x4, x, value),
+            Self::SkipIfRegistersEqual { x, y } => xy(0x5, x, y, 0x0),
+            Self::Load { x, value } => x_low(0x6, x, value),
+            Self::Add { x, value } => x_low(0x7, x, value),
+            Self::Move { x, y } => xy(0x8, x, y, 0x0),
+            Self::Or { x, y } => xy(0x8, x, y, 0x1),
+            Self::And { x, y } => xy(0x8, x, y, 0x2),
+            Self::Xor { x, y } => xy(0x8, x, y, 0x3),
+            Self::AddRegisters { x, y } => xy(0x8, x, y, 0x4),
+            Self::Subtract { x, y } => xy(0x8, x, y, 0x5),
+            Self::ShiftRight { x, y } => xy(0x8, x, y, 0x6),
+            Self::SubtractReverse { x, y } => xy(0x8, x, y, 0x7),
+            Self::ShiftLeft { x, y } => xy(0x8, x, y, 0xE),
+            Self::SkipIfRegistersNotEqual { x, y } => xy(0x9, x, y, 0x0),
+            Self::LoadIndex { address } => nnn(0xA, address),
+            Self::JumpOffset { address } => nnn(0xB, address),
+            Self::Random { x, mask } => x_low(0xC, x, mask),
+            Self::Draw { x, y, n } => xy(0xD, x, y, (n & 0xF) as u16),
+            Self::SkipIfKey { x } => x_low(0xE, x, 0x9E),
+            Self::SkipIfNotKey { x } => x_low(0xE, x, 0xA1),
+            Self::LoadDelay { x } => x_low(0xF, x, 0x07),
+            Self::WaitKey { x } => x_low(0xF, x, 0x0A),
+            Self::SetDelay { x } => x_low(0xF, x, 0x15),
+            Self::SetSound { x } => x_low(0xF, x, 0x18),
+            Self::AddIndex { x } => x_low(0xF, x, 0x1E),
+            Self::LoadFont { x } => x_low(0xF, x, 0x29),
+            Self::Bcd { x } => x_low(0xF, x, 0x33),
+            Self::SaveRegisters { x } => x_low(0xF, x, 0x55),
+            Self::LoadRegisters { x } => x_low(0xF, x, 0x65),
+            Self::HiresClear => 0x0230,
+            Self::ScrollDown { n } => 0x00C0 | (n & 0xF) as u16,
+            Self::ScrollRight => 0x00FB,
+            Self::ScrollLeft => 0x00FC,
+            Self::Exit => 0x00FD,
+            Self::Lores => 0x00FE,
+            Self::Hires => 0x00FF,
+            Self::LoadBigFont { x } => x_low(0xF, x, 0x30),
+            Self::SaveFlags { x } => x_low(0xF, x, 0x75),
+            Self::LoadFlags { x } => x_low(0xF, x, 0x85),
+            Self::ScrollUp { n } => 0x00D0 | (n & 0xF) as u16,
+            Self::SaveRange { x, y } => xy(0x5, x, y, 0x2),
+            Self::LoadRange { x, y } => xy(0x5, x, y, 0x3),
+            Self::LongIndex => 0xF000,
+            Self::Plane { n } => x_low(0xF, n, 0x01),
+            Self::Audio => 0xF002,
+            Self::Pitch { x } => x_low(0xF, x, 0x3A),
+            Self::StepBackground => 0x02A0,
+            Self::AddNibbles { x, y } => xy(0x5, x, y, 0x1),
+            Self::ColorZones { x, y } => xy(0xB, x, y, 0x0),
+            Self::ColorRows { x, y, n } => xy(0xB, x, y, (n & 0xF) as u16),
+            Self::SkipIfKey2 { x } => x_low(0xE, x, 0xF2),
+            Self::SkipIfNotKey2 { x } => x_low(0xE, x, 0xF5),
+            Self::Stop => 0x00ED,
+            Self::Nop => 0x00F2,
+            Self::WaitDelay => 0x0151,
+            Self::SkipNext => 0x0188,
+            Self::SkipIfGreater { x, y } => xy(0x5, x, y, 0x1),
+            Self::JumpBack { offset } => 0xBB00 | offset as u16,
+            Self::JumpForward { offset } => 0xBF00 | offset as u16,
+            Self::Output { x } => x_low(0xF, x, 0x03),
+            Self::SkipBytes { x } => x_low(0xF, x, 0x1B),
+            Self::Delay { x } => x_low(0xF, x, 0x4F),
+            Self::WaitInput { x } => x_low(0xF, x, 0xE3),
+            Self::Input { x } => x_low(0xF, x, 0xE7),
+            #[cfg(feature = "alloc")]
+            Self::MegaChip(instruction) => instruction.encode(),
+        }
+    }
+
+    /// Length in bytes, including the address that follows the long index loads
+    pub fn size(&self) -> usize {
+        match self {
+            Self::LongIndex => 4,
+            #[cfg(feature = "alloc")]
+            Self::MegaChip(MegaChipInstruction::LongIndex { .. }) => 4,
+            _ => 2,
+        }
+    }
+}
+
+impl core::fmt::Display for Instruction {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match *self {
+            Self::Clear => write!(f, "clear"),
+            Self::Return => write!(f, "return"),
+            Self::MachineCode { address } => write!(f, "native 0x{:0>3X}", address),
+            Self::Jump { address } => write!(f, "jump 0x{:0>3X}", address),
+            Self::Call { address } => write!(f, ":call 0x{:0>3X}", address),
+            Self::SkipIfEqual { x, value } => write!(f, "if v{:X} != 0x{:0>2X} then", x, value),
+            Self::SkipIfNotEqual { x, value } => {
+                write!(f, "if v{:X} == 0x{:0>2X} then", x, value)
+            }
+            Self::SkipIfRegistersEqual { x, y } => write!(f, "if v{:X} != v{:X} then", x, y),
+            Self::Load { x, value } => write!(f, "v{:X} := 0x{:0>2X}", x, value),
+            Self::Add { x, value } => write!(f, "v{:X} += 0x{:0>2X}", x, value),
+            Self::Move { x, y } => write!(f, "v{:X} := v{:X}", x, y),
+            Self::Or { x, y } => write!(f, "v{:X} |= v{:X}", x, y),
+            Self::And { x, y } => write!(f, "v{:X} &= v{:X}", x, y),
+            Self::Xor { x, y } => write!(f, "v{:X} ^= v{:X}", x, y),
+            Self::AddRegisters { x, y } => write!(f, "v{:X} += v{:X}", x, y),
+            Self::Subtract { x, y } => write!(f, "v{:X} -= v{:X}", x, y),
+            Self::ShiftRight { x, y } => write!(f, "v{:X} >>= v{:X}", x, y),
+            Self::SubtractReverse { x, y } => write!(f, "v{:X} =- v{:X}", x, y),
+            Self::ShiftLeft { x, y } => write!(f, "v{:X} <<= v{:X}", x, y),
+            Self::SkipIfRegistersNotEqual { x, y } => write!(f, "if v{:X} == v{:X} then", x, y),
+            Self::LoadIndex { address } => write!(f, "i := 0x{:0>3X}", address),
+            Self::JumpOffset { address } => write!(f, "jump0 0x{:0>3X}", address),
+            Self::Random { x, mask } => write!(f, "v{:X} := random 0x{:0>2X}", x, mask),
+            Self::Draw { x, y, n } => write!(f, "sprite v{:X} v{:X} {}", x, y, n),
+            Self::SkipIfKey { x } => write!(f, "if v{:X} -key then", x),
+            Self::SkipIfNotKey { x } => write!(f, "if v{:X} key then", x),
+            Self::LoadDelay { x } => write!(f, "v{:X} := delay", x),
+            Self::WaitKey { x } => write!(f, "v{:X} := key", x),
+            Self::SetDelay { x } => write!(f, "delay := v{:X}", x),
+            Self::SetSound { x } => write!(f, "buzzer := v{:X}", x),
+            Self::AddIndex { x } => write!(f, "i += v{:X}", x),
+            Self::LoadFont { x } => write!(f, "i := hex v{:X}", x),
+            Self::Bcd { x } => write!(f, "bcd v{:X}", x),
+            Self::SaveRegisters { x } => write!(f, "save v{:X}", x),
+            Self::LoadRegisters { x } => write!(f, "load v{:X}", x),
+            Self::HiresClear => write!(f, "hires-clear"),
+            Self::ScrollDown { n } => write!(f, "scroll-down {}", n),
+            Self::ScrollRight => write!(f, "scroll-right"),
+            Self::ScrollLeft => write!(f, "scroll-left"),
+            Self::Exit => write!(f, "exit"),
+            Self::Lores => write!(f, "lores"),
+            Self::Hires => write!(f, "hires"),
+            Self::LoadBigFont { x } => write!(f, "i := bighex v{:X}", x),
+            Self::SaveFlags { x } => write!(f, "saveflags v{:X}", x),
+            Self::LoadFlags { x } => write!(f, "loadflags v{:X}", x),
+            Self::ScrollUp { n } => write!(f, "scroll-up {}", n),
+            Self::SaveRange { x, y } => write!(f, "save v{:X} - v{:X}", x, y),
+            Self::LoadRange { x, y } => write!(f, "load v{:X} - v{:X}", x, y),
+            Self::LongIndex => write!(f, "i := long"),
+            Self::Plane { n } => write!(f, "plane {}", n),
+            Self::Audio => write!(f, "audio"),
+            Self::Pitch { x } => write!(f, "pitch := v{:X}", x),
+            Self::StepBackground => write!(f, "background"),
+            Self::AddNibbles { x, y } => write!(f, "v{:X} +nibbles v{:X}", x, y),
+            Self::ColorZones { x, y } => write!(f, "color-zones v{:X} v{:X}", x, y),
+            Self::ColorRows { x, y, n } => write!(f, "color-rows v{:X} v{:X} {}", x, y, n),
+            Self::SkipIfKey2 { x } => write!(f, "if v{:X} -key2 then", x),
+            Self::SkipIfNotKey2 { x } => write!(f, "if v{:X} key2 then", x),
+            Self::Stop => write!(f, "stop"),
+            Self::Nop => write!(f, "nop"),
+            Self::WaitDelay => write!(f, "wait-delay"),
+            Self::SkipNext => write!(f, "skip"),
+            Self::SkipIfGreater { x, y } => write!(f, "if v{:X} <= v{:X} then", x, y),
+            Self::JumpBack { offset } => write!(f, "jump-back {}", offset),
+            Self::JumpForward { offset } => write!(f, "jump-forward {}", offset),
+            Self::Output { x } => write!(f, "out v{:X}", x),
+            Self::SkipBytes { x } => write!(f, "skip v{:X}", x),
+            Self::Delay { x } => write!(f, "delay-wait v{:X}", x),
+            Self::WaitInput { x } => write!(f, "v{:X} := in-strobe", x),
+            Self::Input { x } => write!(f, "v{:X} := in", x),
+            #[cfg(feature = "alloc")]
+            Self::MegaChip(instruction) => instruction.fmt(f),
+        }
+    }
+}
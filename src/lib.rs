@@ -1,575 +1,1804 @@
-#![no_std]
-
-pub struct Timer {
-    raw: u128,
-    length: u128,
-}
-
-impl Timer {
-    /// Length is in microseconds
-    pub fn new(length: u128) -> Self {
-        Self { raw: 0, length }
-    }
-    pub fn check(&mut self, delta: u128) -> bool {
-        self.raw += delta;
-
-        if self.raw >= self.length {
-            self.raw = 0;
-            true
-        } else {
-            false
-        }
-    }
-}
-#[derive(Debug)]
-pub enum Chip8Error {
-    InvalidInstruction {
-        pc: usize,
-        nibble_0: u8,
-        nibble_1: u8,
-        nibble_2: u8,
-        nibble_3: u8,
-    },
-    PopEmptyStack {
-        pc: usize,
-        nibble_0: u8,
-        nibble_1: u8,
-        nibble_2: u8,
-        nibble_3: u8,
-    },
-}
-impl core::fmt::Display for Chip8Error {
-    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        match self {
-            Chip8Error::InvalidInstruction {
-                pc,
-                nibble_0,
-                nibble_1,
-                nibble_2,
-                nibble_3,
-            } => {
-                write!(
-                    f,
-                    "[pc = {:0>3X}]; Illegal instruction: {:0>1X}{:0>1X}{:0>1X}{:0>1X}",
-                    pc, nibble_0, nibble_1, nibble_2, nibble_3
-                )
-            }
-            Chip8Error::PopEmptyStack {
-                pc,
-                nibble_0,
-                nibble_1,
-                nibble_2,
-                nibble_3,
-            } => {
-                write!(
-                    f,
-                    "[pc = {:0>3X}]; Tried to pop an empty stack: {:0>1X}{:0>1X}{:0>1X}{:0>1X}",
-                    pc, nibble_0, nibble_1, nibble_2, nibble_3
-                )
-            }
-        }
-    }
-}
-
-impl core::error::Error for Chip8Error {}
-
-pub struct Chip8Config {
-    pub instructions_per_second: usize,
-    pub program_start: usize,
-    pub font: [u8; Self::FONT_CHAR_SIZE * 16],
-    pub font_start: usize,
-
-    // Backwards-compat flags
-    pub copy_vy_while_shifting: bool,
-    pub increment_index_during_save_load: bool,
-    pub index_overflow_flag: bool,
-}
-impl Default for Chip8Config {
-    fn default() -> Self {
-        Self {
-            instructions_per_second: Self::INSTRUCTIONS_PER_SECOND,
-            program_start: Self::PROGRAM_START,
-            font: Self::DEFAULT_FONT,
-            font_start: Self::FONT_START,
-            copy_vy_while_shifting: false,
-            increment_index_during_save_load: false,
-            index_overflow_flag: false,
-        }
-    }
-}
-
-impl Chip8Config {
-    pub const INSTRUCTIONS_PER_SECOND: usize = 700;
-    pub const PROGRAM_START: usize = 0x200;
-    /// In bytes
-    pub const FONT_CHAR_SIZE: usize = 5;
-    pub const DEFAULT_FONT: [u8; Self::FONT_CHAR_SIZE * 16] = [
-        0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-        0x20, 0x60, 0x20, 0x20, 0x70, // 1
-        0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-        0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-        0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-        0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-        0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-        0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-        0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-        0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-        0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-        0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-        0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-        0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-        0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-        0xF0, 0x80, 0xF0, 0x80, 0x80, // F
-    ];
-    pub const FONT_START: usize = 0x050;
-}
-
-pub struct Chip8 {
-    pub framebuffer: [bool; Self::WIDTH * Self::HEIGHT],
-    keys: [bool; 16],
-    pc: usize,
-    memory: [u8; Self::MEMORY_SIZE],
-    index_reg: u16,
-    stack: [u16; Self::STACK_SIZE / 2],
-    stack_len: usize,
-    delay_timer: u8,
-    sound_timer: u8,
-    ds_timer: Timer, // delay-sound timer
-    program_timer: Timer,
-    variable_reg: [u8; 16],
-    config: Chip8Config,
-    keypress_this_frame: Option<u8>,
-}
-
-impl Chip8 {
-    pub const WIDTH: usize = 64;
-    pub const HEIGHT: usize = 32;
-    /// In bytes
-    pub const MEMORY_SIZE: usize = 4096;
-    /// In bytes
-    pub const STACK_SIZE: usize = 2048;
-
-    pub fn new(config: Chip8Config) -> Self {
-        let mut memory = [0; Self::MEMORY_SIZE];
-        memory[config.font_start..config.font_start + config.font.len()]
-            .copy_from_slice(&config.font);
-        // assert!(memory[0x050] == 0xF0); // Assertion for default config
-        Self {
-            framebuffer: [false; Self::WIDTH * Self::HEIGHT],
-            keys: [false; 16],
-            pc: config.program_start,
-            memory,
-            index_reg: 0,
-            stack: [0; Self::STACK_SIZE / 2],
-            stack_len: 0,
-            delay_timer: 0,
-            sound_timer: 0,
-            variable_reg: [0; 16],
-            ds_timer: Timer::new(1_000_000 / 60),
-            program_timer: Timer::new(1_000_000 / config.instructions_per_second as u128),
-            config,
-            keypress_this_frame: None,
-        }
-    }
-
-    pub fn set_program(&mut self, program: &[u8]) {
-        self.memory[self.config.program_start..self.config.program_start + program.len()]
-            .copy_from_slice(program);
-    }
-    pub fn should_play_sound(&self) -> bool {
-        self.sound_timer > 0
-    }
-
-    pub fn press(&mut self, key: u8) {
-        self.keys[key as usize] = true;
-        self.keypress_this_frame = Some(key);
-    }
-
-    pub fn release(&mut self, key: u8) {
-        self.keys[key as usize] = false;
-    }
-
-    /// `delta` is in microseconds
-    pub fn update(
-        &mut self,
-        delta: u128,
-        random_source: impl FnOnce() -> u8,
-    ) -> Result<(), Chip8Error> {
-        let keypress = self.keypress_this_frame.take();
-        if self.ds_timer.check(delta) {
-            if self.delay_timer > 0 {
-                self.delay_timer -= 1;
-            }
-            if self.sound_timer > 0 {
-                self.sound_timer -= 1;
-            }
-        }
-
-        if self.program_timer.check(delta) {
-            let nibble_0 = self.memory[self.pc] >> 4;
-            let nibble_1 = self.memory[self.pc] & 0b00001111;
-            let nibble_2 = self.memory[self.pc + 1] >> 4;
-            let nibble_3 = self.memory[self.pc + 1] & 0b00001111;
-            self.pc += 2;
-            match nibble_0 {
-                0x0 => {
-                    if nibble_1 == 0x0 {
-                        if nibble_2 == 0xE {
-                            if nibble_3 == 0xE {
-                                // INST 00EE
-                                if self.stack_len == 0 {
-                                    return Err(Chip8Error::PopEmptyStack {
-                                        pc: self.pc - 2,
-                                        nibble_0,
-                                        nibble_1,
-                                        nibble_2,
-                                        nibble_3,
-                                    });
-                                }
-                                self.pc = self.stack[self.stack_len - 1] as usize;
-                                self.stack_len -= 1;
-                            } else if nibble_3 == 0x0 {
-                                // INST 00E0 : clear
-                                self.framebuffer.fill(false);
-                            } else {
-                                return Err(Chip8Error::InvalidInstruction {
-                                    pc: self.pc - 2,
-                                    nibble_0,
-                                    nibble_1,
-                                    nibble_2,
-                                    nibble_3,
-                                });
-                            }
-                        } else {
-                            return Err(Chip8Error::InvalidInstruction {
-                                pc: self.pc - 2,
-                                nibble_0,
-                                nibble_1,
-                                nibble_2,
-                                nibble_3,
-                            });
-                        }
-                    } else {
-                        return Err(Chip8Error::InvalidInstruction {
-                            pc: self.pc - 2,
-                            nibble_0,
-                            nibble_1,
-                            nibble_2,
-                            nibble_3,
-                        });
-                    }
-                }
-                0x1 => {
-                    // INST 1NNN : jump NNN
-                    let nnn =
-                        ((nibble_1 as u16) << 8) | ((nibble_2 as u16) << 4) | (nibble_3 as u16);
-                    self.pc = nnn as usize;
-                }
-                0x2 => {
-                    // INST 2NNN
-                    let nnn =
-                        ((nibble_1 as u16) << 8) | ((nibble_2 as u16) << 4) | (nibble_3 as u16);
-                    self.stack[self.stack_len] = self.pc as u16;
-                    self.stack_len += 1;
-                    self.pc = nnn as usize;
-                }
-                0x3 => {
-                    // INST 3XNN : if vx != NN then
-                    let vx = self.variable_reg[nibble_1 as usize];
-                    let nn = (nibble_2 << 4) | (nibble_3);
-                    if vx == nn {
-                        self.pc += 2;
-                    }
-                }
-                0x4 => {
-                    // INST 4XNN : if vx == NN then
-                    let vx = self.variable_reg[nibble_1 as usize];
-                    let nn = (nibble_2 << 4) | (nibble_3);
-                    if vx != nn {
-                        self.pc += 2;
-                    }
-                }
-                0x5 => {
-                    // INST 5XY0 : if vx != vy then
-                    let vx = self.variable_reg[nibble_1 as usize];
-                    let vy = self.variable_reg[nibble_2 as usize];
-                    if vx == vy {
-                        self.pc += 2;
-                    }
-                }
-                0x6 => {
-                    // INST 6XNN : vx := NN
-                    let vx = &mut self.variable_reg[nibble_1 as usize];
-                    let nn = (nibble_2 << 4) | (nibble_3);
-                    *vx = nn;
-                }
-                0x7 => {
-                    // INST 7XNN : vx += NN
-                    let vx = &mut self.variable_reg[nibble_1 as usize];
-                    let nn = (nibble_2 << 4) | (nibble_3);
-                    *vx += nn;
-                }
-                0x8 => {
-                    let vy = self.variable_reg[nibble_2 as usize];
-                    let vx = &mut self.variable_reg[nibble_1 as usize];
-                    match nibble_3 {
-                        0x0 => {
-                            // INST 8XY0 : vx := vy
-                            *vx = vy;
-                        }
-                        0x1 => {
-                            // INST 8XY1 : vx |= vy
-                            *vx |= vy;
-                        }
-                        0x2 => {
-                            // INST 8XY2 : vx &= vy
-                            *vx &= vy;
-                        }
-                        0x3 => {
-                            // INST 8XY3 : vx ^= vy
-                            *vx ^= vy;
-                        }
-                        0x4 => {
-                            // INST 8XY4 : vx += vy
-                            *vx += vy;
-                        }
-                        0x5 => {
-                            // INST 8XY5 : vx -= vy
-                            *vx -= vy;
-                        }
-                        0x6 => {
-                            // INST 8XY6 : vx >>= vy
-                            self.variable_reg[0xF] = *vx & 0b1;
-                            if self.config.copy_vy_while_shifting {
-                                // LEGACY : Old interpreters would copy vy into vx before shifting
-                                let vy = self.variable_reg[nibble_2 as usize];
-                                let vx = &mut self.variable_reg[nibble_1 as usize];
-                                *vx = vy;
-                                *vx >>= 1;
-                            } else {
-                                let vx = &mut self.variable_reg[nibble_1 as usize];
-                                *vx >>= 1;
-                            }
-                        }
-                        0x7 => {
-                            // INST 8XY7 : vx = vy - vx
-                            *vx = vy - *vx;
-                        }
-                        0xE => {
-                            // INST 8XYE : vx <<= vy
-                            self.variable_reg[0xF] = *vx & 0b10000000;
-                            if self.config.copy_vy_while_shifting {
-                                // LEGACY : Old interpreters would copy vy into vx before shifting
-                                let vy = self.variable_reg[nibble_2 as usize];
-                                let vx = &mut self.variable_reg[nibble_1 as usize];
-                                *vx = vy;
-                                *vx <<= 1;
-                            } else {
-                                let vx = &mut self.variable_reg[nibble_1 as usize];
-                                *vx <<= 1;
-                            }
-                        }
-                        _ => {
-                            return Err(Chip8Error::InvalidInstruction {
-                                pc: self.pc - 2,
-                                nibble_0,
-                                nibble_1,
-                                nibble_2,
-                                nibble_3,
-                            });
-                        }
-                    }
-                }
-                0x9 => {
-                    // INST 9XY0 : if vx == vy then
-                    let vx = self.variable_reg[nibble_1 as usize];
-                    let vy = self.variable_reg[nibble_2 as usize];
-                    if vx != vy {
-                        self.pc += 2;
-                    }
-                }
-                0xA => {
-                    // INST ANNN : index_reg := NNN
-                    let nnn =
-                        ((nibble_1 as u16) << 8) | ((nibble_2 as u16) << 4) | (nibble_3 as u16);
-                    self.index_reg = nnn;
-                }
-                0xB => {
-                    // INST BNNN : jump NNN + v0
-                    let nnn =
-                        ((nibble_1 as u16) << 8) | ((nibble_2 as u16) << 4) | (nibble_3 as u16);
-                    let v0 = self.variable_reg[0x0] as u16;
-                    self.pc = (nnn + v0) as usize;
-                }
-                0xC => {
-                    // INST CXNN
-                    let vx = &mut self.variable_reg[nibble_1 as usize];
-                    let nn = (nibble_2 << 4) | (nibble_3);
-                    *vx = random_source() & nn;
-                }
-                0xD => {
-                    // INST DXYN : sprite vx vy N
-                    let vx = self.variable_reg[nibble_1 as usize];
-                    let vy = self.variable_reg[nibble_2 as usize];
-                    let n = nibble_3 as u16;
-
-                    let x = vx as usize % Self::WIDTH;
-                    let mut y = vy as usize % Self::HEIGHT;
-                    self.variable_reg[0xF] = 0;
-                    for i in 0..n {
-                        let data = self.memory[(self.index_reg + i) as usize];
-                        let mut x = x;
-                        for j in 0..8 {
-                            let data_bit = ((0b10000000 >> j) & data) > 0;
-                            let current_pixel = &mut self.framebuffer[y * Self::WIDTH + x];
-                            if data_bit {
-                                if *current_pixel {
-                                    *current_pixel = false;
-                                    self.variable_reg[0xF] = 1;
-                                } else {
-                                    *current_pixel = true;
-                                }
-                            }
-                            x += 1;
-                            if x >= Self::WIDTH {
-                                break;
-                            }
-                        }
-                        y += 1;
-                        if y >= Self::HEIGHT {
-                            break;
-                        }
-                    }
-                }
-                0xE => {
-                    let vx = self.variable_reg[nibble_1 as usize];
-                    if vx < self.keys.len() as u8 {
-                        if nibble_2 == 0x9 && nibble_3 == 0xE {
-                            // INST EX9E : if key = vx not pressed then
-                            if self.keys[vx as usize] {
-                                self.pc += 2;
-                            }
-                        } else if nibble_2 == 0xA && nibble_3 == 0x1 {
-                            // INST EXA1 : if key = vx is pressed then
-                            if !self.keys[vx as usize] {
-                                self.pc += 2;
-                            }
-                        } else {
-                            return Err(Chip8Error::InvalidInstruction {
-                                pc: self.pc - 2,
-                                nibble_0,
-                                nibble_1,
-                                nibble_2,
-                                nibble_3,
-                            });
-                        }
-                    } else {
-                        // eprintln!("[WARN] Tried to check whether key {vx} is pressed but max value of key is {}.", self.keys.len())
-                    }
-                }
-                0xF => {
-                    let vx = &mut self.variable_reg[nibble_1 as usize];
-                    if nibble_2 == 0x0 {
-                        if nibble_3 == 0x7 {
-                            // INST FX07 : vx := delay
-                            *vx = self.delay_timer;
-                        } else if nibble_3 == 0xA {
-                            // INST FX0A : vx := key // Wait for a keypress
-                            if let Some(keypress) = keypress {
-                                *vx = keypress;
-                            } else {
-                                self.pc -= 2; // wait
-                            }
-                        } else {
-                            return Err(Chip8Error::InvalidInstruction {
-                                pc: self.pc - 2,
-                                nibble_0,
-                                nibble_1,
-                                nibble_2,
-                                nibble_3,
-                            });
-                        }
-                    } else if nibble_2 == 0x1 {
-                        if nibble_3 == 0x5 {
-                            // INST FX15 : delay := vx
-                            self.delay_timer = *vx;
-                        } else if nibble_3 == 0x8 {
-                            // INST FX18 : sound := vx
-                            self.sound_timer = *vx;
-                        } else if nibble_3 == 0xE {
-                            // INST FX1E : index_reg += vx
-                            self.index_reg += *vx as u16;
-                            if self.config.index_overflow_flag {
-                                // LEGACY : The interpreter for Amiga would treat index_reg going above 0x0FFF as a special overflow and would set vf := 1 in that case
-                                // The game called "Spacefight 2091!" relies on this.
-                                if self.index_reg > 0x0FFF {
-                                    self.variable_reg[0xF] = 1;
-                                }
-                            }
-                        } else {
-                            return Err(Chip8Error::InvalidInstruction {
-                                pc: self.pc - 2,
-                                nibble_0,
-                                nibble_1,
-                                nibble_2,
-                                nibble_3,
-                            });
-                        }
-                    } else if nibble_2 == 0x2 && nibble_3 == 0x9 {
-                        // INST FX29 : index_reg := hex vx
-                        let vx = self.variable_reg[nibble_1 as usize];
-                        let ch = (vx & 0b00001111) as u16;
-                        self.index_reg =
-                            self.config.font_start as u16 + ch * Chip8Config::FONT_CHAR_SIZE as u16;
-                    } else if nibble_2 == 0x3 && nibble_3 == 0x3 {
-                        // INST FX33 : bcd vx // Decode vx into binary-coded decimal
-                        let vx = self.variable_reg[nibble_1 as usize];
-                        self.memory[self.index_reg as usize] = vx / 100;
-                        self.memory[self.index_reg as usize + 1] = (vx / 10) % 10;
-                        self.memory[self.index_reg as usize + 2] = (vx % 100) % 10;
-                    } else if nibble_2 == 0x5 && nibble_3 == 0x5 {
-                        // INST FX55 : save vx // Save v0-vx to index_reg through (index_reg+x)
-                        for x in 0..=nibble_1 as usize {
-                            if self.config.increment_index_during_save_load {
-                                // LEGACY : Old interpreters used to increment the index register along the way.
-                                self.memory[self.index_reg as usize] = self.variable_reg[x];
-                                self.index_reg += 1;
-                            } else {
-                                self.memory[self.index_reg as usize + x] = self.variable_reg[x];
-                            }
-                        }
-                    } else if nibble_2 == 0x6 && nibble_3 == 0x5 {
-                        // INST FX65 : load vx // Load v0-vx from index_reg through (index_reg+x)
-                        for x in 0..=nibble_1 as usize {
-                            if self.config.increment_index_during_save_load {
-                                // LEGACY : Old interpreters used to increment the index register along the way.
-                                self.variable_reg[x] = self.memory[self.index_reg as usize];
-                                self.index_reg += 1;
-                            } else {
-                                self.variable_reg[x] = self.memory[self.index_reg as usize + x];
-                            }
-                        }
-                    } else {
-                        return Err(Chip8Error::InvalidInstruction {
-                            pc: self.pc - 2,
-                            nibble_0,
-                            nibble_1,
-                            nibble_2,
-                            nibble_3,
-                        });
-                    }
-                }
-                _ => {
-                    return Err(Chip8Error::InvalidInstruction {
-                        pc: self.pc - 2,
-                        nibble_0,
-                        nibble_1,
-                        nibble_2,
-                        nibble_3,
-                    });
-                }
-            }
-        }
-        Ok(())
-    }
-}
+#![no_std]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+mod detect;
+mod instruction;
+#[cfg(feature = "alloc")]
+mod megachip;
+mod state;
+#[cfg(feature = "std")]
+mod storage;
+mod vip;
+
+pub use detect::Candidate;
+#[cfg(feature = "alloc")]
+pub use instruction::MegaChipInstruction;
+pub use instruction::{DecodeError, Instruction};
+#[cfg(feature = "std")]
+pub use storage::FileFlagStorage;
+pub use vip::CosmacVip;
+
+/// Emulated memory, only as large as the config needs with the `alloc` feature and room for
+/// `Chip8::MAX_MEMORY_SIZE` bytes without it
+#[cfg(feature = "alloc")]
+type Memory = alloc::boxed::Box<[u8]>;
+#[cfg(not(feature = "alloc"))]
+type Memory = [u8; Chip8::MAX_MEMORY_SIZE];
+
+pub struct Timer {
+    /// Elapsed time, `step` is added for every microsecond and a tick is taken every `period`.
+    /// The remainder is never lost, so a frequency that isn't a whole number of microseconds stays exact.
+    raw: u64,
+    step: u64,
+    period: u64,
+}
+
+impl Timer {
+    const MICROS_PER_SECOND: u64 = 1_000_000;
+
+    /// Length is in microseconds
+    pub fn new(length: u128) -> Self {
+        Self {
+            raw: 0,
+            step: 1,
+            period: u64::try_from(length).unwrap_or(u64::MAX).max(1),
+        }
+    }
+    /// Frequency is in Hz
+    pub fn with_frequency(frequency: u32) -> Self {
+        Self {
+            raw: 0,
+            step: frequency as u64,
+            period: Self::MICROS_PER_SECOND,
+        }
+    }
+    /// Adds `delta` microseconds and takes one tick if it is due, the remainder carries over
+    pub fn check(&mut self, delta: u128) -> bool {
+        self.add(delta);
+        self.tick()
+    }
+    /// Adds `delta` microseconds
+    pub fn add(&mut self, delta: u128) {
+        let delta = u64::try_from(delta).unwrap_or(u64::MAX);
+        self.raw = self.raw.saturating_add(delta.saturating_mul(self.step));
+    }
+    /// Takes one tick if it is due
+    pub fn tick(&mut self) -> bool {
+        if self.raw >= self.period {
+            self.raw -= self.period;
+            true
+        } else {
+            false
+        }
+    }
+    /// Microseconds until the next tick is due, rounded up
+    pub fn until_tick(&self) -> u32 {
+        let left = self.period.saturating_sub(self.raw);
+        u32::try_from(left.div_ceil(self.step.max(1))).unwrap_or(u32::MAX)
+    }
+}
+#[derive(Clone, Copy)]
+enum KeyEvent {
+    Press(u8),
+    Release(u8),
+}
+
+/// Fixed size FIFO of key events, the oldest event is dropped when it is full
+struct KeyQueue {
+    events: [KeyEvent; Self::CAPACITY],
+    start: usize,
+    len: usize,
+}
+
+impl KeyQueue {
+    const CAPACITY: usize = 16;
+
+    const fn new() -> Self {
+        Self {
+            events: [KeyEvent::Release(0); Self::CAPACITY],
+            start: 0,
+            len: 0,
+        }
+    }
+    fn push(&mut self, event: KeyEvent) {
+        if self.len == Self::CAPACITY {
+            self.pop();
+        }
+        self.events[(self.start + self.len) % Self::CAPACITY] = event;
+        self.len += 1;
+    }
+    fn pop(&mut self) -> Option<KeyEvent> {
+        if self.len == 0 {
+            return None;
+        }
+        let event = self.events[self.start];
+        self.start = (self.start + 1) % Self::CAPACITY;
+        self.len -= 1;
+        Some(event)
+    }
+    fn clear(&mut self) {
+        self.start = 0;
+        self.len = 0;
+    }
+}
+
+#[derive(Debug)]
+pub enum Chip8Error {
+    InvalidInstruction {
+        pc: usize,
+        nibble_0: u8,
+        nibble_1: u8,
+        nibble_2: u8,
+        nibble_3: u8,
+    },
+    PopEmptyStack {
+        pc: usize,
+        nibble_0: u8,
+        nibble_1: u8,
+        nibble_2: u8,
+        nibble_3: u8,
+    },
+    StackOverflow {
+        pc: usize,
+        nibble_0: u8,
+        nibble_1: u8,
+        nibble_2: u8,
+        nibble_3: u8,
+    },
+    MemoryOutOfBounds {
+        pc: usize,
+        address: usize,
+    },
+    ProgramTooLarge {
+        size: usize,
+        max_size: usize,
+    },
+    /// A `FlagStorage` couldn't save or load the RPL user flags
+    FlagStorage,
+    /// `Chip8::save_state` needs `needed` bytes
+    StateBufferTooSmall {
+        size: usize,
+        needed: usize,
+    },
+    /// `Chip8::load_state` was given data that isn't a save state, or a damaged one
+    InvalidState,
+    /// The save state was written by a version of `Chip8::save_state` this one can't read
+    UnsupportedStateVersion {
+        version: u8,
+    },
+}
+impl Chip8Error {
+    fn nibbles(opcode: u16) -> [u8; 4] {
+        let [high, low] = opcode.to_be_bytes();
+        [high >> 4, high & 0xF, low >> 4, low & 0xF]
+    }
+    fn invalid_instruction(pc: usize, opcode: u16) -> Self {
+        let [nibble_0, nibble_1, nibble_2, nibble_3] = Self::nibbles(opcode);
+        Chip8Error::InvalidInstruction {
+            pc,
+            nibble_0,
+            nibble_1,
+            nibble_2,
+            nibble_3,
+        }
+    }
+    fn pop_empty_stack(pc: usize, opcode: u16) -> Self {
+        let [nibble_0, nibble_1, nibble_2, nibble_3] = Self::nibbles(opcode);
+        Chip8Error::PopEmptyStack {
+            pc,
+            nibble_0,
+            nibble_1,
+            nibble_2,
+            nibble_3,
+        }
+    }
+    fn stack_overflow(pc: usize, opcode: u16) -> Self {
+        let [nibble_0, nibble_1, nibble_2, nibble_3] = Self::nibbles(opcode);
+        Chip8Error::StackOverflow {
+            pc,
+            nibble_0,
+            nibble_1,
+            nibble_2,
+            nibble_3,
+        }
+    }
+}
+impl core::fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Chip8Error::InvalidInstruction {
+                pc,
+                nibble_0,
+                nibble_1,
+                nibble_2,
+                nibble_3,
+            } => {
+                write!(
+                    f,
+                    "[pc = {:0>3X}]; Illegal instruction: {:0>1X}{:0>1X}{:0>1X}{:0>1X}",
+                    pc, nibble_0, nibble_1, nibble_2, nibble_3
+                )
+            }
+            Chip8Error::PopEmptyStack {
+                pc,
+                nibble_0,
+                nibble_1,
+                nibble_2,
+                nibble_3,
+            } => {
+                write!(
+                    f,
+                    "[pc = {:0>3X}]; Tried to pop an empty stack: {:0>1X}{:0>1X}{:0>1X}{:0>1X}",
+                    pc, nibble_0, nibble_1, nibble_2, nibble_3
+                )
+            }
+            Chip8Error::StackOverflow {
+                pc,
+                nibble_0,
+                nibble_1,
+                nibble_2,
+                nibble_3,
+            } => {
+                write!(
+                    f,
+                    "[pc = {:0>3X}]; Tried to push to a full stack: {:0>1X}{:0>1X}{:0>1X}{:0>1X}",
+                    pc, nibble_0, nibble_1, nibble_2, nibble_3
+                )
+            }
+            Chip8Error::MemoryOutOfBounds { pc, address } => {
+                write!(
+                    f,
+                    "[pc = {:0>3X}]; Tried to access memory out of bounds: {:0>3X}",
+                    pc, address
+                )
+            }
+            Chip8Error::ProgramTooLarge { size, max_size } => {
+                write!(
+                    f,
+                    "Program is {} bytes but only {} bytes fit in memory",
+                    size, max_size
+                )
+            }
+            Chip8Error::FlagStorage => {
+                write!(f, "Couldn't save or load the RPL user flags")
+            }
+            Chip8Error::StateBufferTooSmall { size, needed } => {
+                write!(
+                    f,
+                    "Save state is {} bytes but the buffer is only {} bytes",
+                    needed, size
+                )
+            }
+            Chip8Error::InvalidState => {
+                write!(f, "Save state is invalid or damaged")
+            }
+            Chip8Error::UnsupportedStateVersion { version } => {
+                write!(f, "Save state version {} is not supported", version)
+            }
+        }
+    }
+}
+
+impl core::error::Error for Chip8Error {}
+
+/// What happens when an instruction accesses memory past `Chip8Config::memory_size`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryAccess {
+    /// Addresses wrap around to the start of memory
+    Wrap,
+    /// `Chip8::update` returns `Chip8Error::MemoryOutOfBounds`
+    Error,
+}
+
+/// What `0NNN` instructions do, they ran native 1802 machine code routines on the COSMAC VIP
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MachineCode {
+    /// The instruction is skipped
+    Ignore,
+    /// `Chip8::update` returns `Chip8Error::InvalidInstruction`
+    Error,
+    /// The instruction is passed to `Host::handler`, see `Chip8::update_with`
+    Custom,
+}
+
+/// The instruction set `Chip8` understands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    /// The original instruction set of the COSMAC VIP
+    Chip8,
+    /// The two-page display variant of the COSMAC VIP interpreter, with a 64x64 display.
+    /// Programs start with `1260`, which jumps to the program at `0x2C0`, and clear the screen with `0230`.
+    Chip8Hires,
+    /// SUPER-CHIP 1.1, adds a 128x64 high resolution mode, scrolling, 16x16 sprites, a big font and RPL user flags
+    SuperChip,
+    /// XO-CHIP, adds 64 KiB of memory, a second bitplane and audio patterns on top of SUPER-CHIP
+    XoChip,
+    /// CHIP-8X for the VP-590 colour board, adds colour zones, a background colour and a second keypad.
+    /// `BNNN` is replaced by the colour instructions and `5XY1` adds the nibbles of vx and vy.
+    Chip8X,
+    /// CHIP-8E, adds comparisons, register range saves and loads, relative jumps, delays and port I/O.
+    /// `BBNN` and `BFNN` are relative jumps, other `BNNN` instructions jump as usual.
+    Chip8E,
+    /// MegaChip, adds a 256x192 colour display and digitised sound on top of SUPER-CHIP.
+    /// Needs the `alloc` feature, memory is still limited to `Chip8::MAX_MEMORY_SIZE` and `01NN NNNN` fails with
+    /// `Chip8Error::MemoryOutOfBounds` for addresses past the end of memory.
+    #[cfg(feature = "alloc")]
+    MegaChip,
+}
+
+impl Variant {
+    fn superchip(self) -> bool {
+        match self {
+            Variant::SuperChip | Variant::XoChip => true,
+            #[cfg(feature = "alloc")]
+            Variant::MegaChip => true,
+            _ => false,
+        }
+    }
+    fn chip8x(self) -> bool {
+        matches!(self, Variant::Chip8X)
+    }
+    fn chip8e(self) -> bool {
+        matches!(self, Variant::Chip8E)
+    }
+    fn xo_chip(self) -> bool {
+        matches!(self, Variant::XoChip)
+    }
+}
+
+/// The parts of a `Chip8` a machine code routine can access
+pub struct Machine<'a> {
+    pub variable_reg: &'a mut [u8; 16],
+    pub index_reg: &'a mut u16,
+    /// `Chip8Config::memory_size` bytes
+    pub memory: &'a mut [u8],
+    pub framebuffer: &'a mut [u8; Chip8::HIRES_WIDTH * Chip8::HIRES_HEIGHT],
+}
+
+pub trait MachineCodeHandler {
+    /// Called for `0NNN` with `address` = NNN
+    fn call(&mut self, address: u16, machine: Machine<'_>) -> Result<(), Chip8Error>;
+}
+
+/// Keeps the SUPER-CHIP RPL user flags around between runs, like the HP48 did
+pub trait FlagStorage {
+    /// Called by `FX75` with all 16 flags
+    fn save(&mut self, flags: &[u8; 16]) -> Result<(), Chip8Error>;
+    /// Called by `FX85`, `flags` holds the current flags and should be left alone if nothing was saved yet
+    fn load(&mut self, flags: &mut [u8; 16]) -> Result<(), Chip8Error>;
+}
+
+/// The parts of the host instructions can call into, passed to `Chip8::update_with`, `Chip8::step_with` and
+/// `Chip8::run_frame_with`
+#[derive(Default)]
+pub struct Host<'a> {
+    /// Gets `0NNN` instructions when `Chip8Config::machine_code` is `MachineCode::Custom`
+    pub handler: Option<&'a mut dyn MachineCodeHandler>,
+    /// `FX75` and `FX85` save and load the RPL user flags through it
+    pub storage: Option<&'a mut dyn FlagStorage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chip8Config {
+    pub variant: Variant,
+    pub instructions_per_second: usize,
+    /// In microseconds, the most time one `Chip8::update` catches up on, anything above is dropped so a
+    /// stalled host doesn't fast-forward the program
+    pub max_catch_up: u32,
+    /// In bytes, capped at `Chip8::MAX_MEMORY_SIZE`
+    pub memory_size: usize,
+    /// Height of the low resolution display, capped at `Chip8::HIRES_HEIGHT`
+    pub display_height: usize,
+    pub program_start: usize,
+    pub font: [u8; Self::FONT_CHAR_SIZE * 16],
+    pub font_start: usize,
+    /// Used by the SUPER-CHIP FX30 instruction
+    pub big_font: [u8; Self::BIG_FONT_CHAR_SIZE * 16],
+    pub big_font_start: usize,
+    /// Maximum number of nested subroutine calls, capped at `Chip8::STACK_SIZE / 2`
+    pub stack_depth: usize,
+    /// Keep the stack inside emulated memory, growing down from this address like the COSMAC VIP's.
+    /// Return addresses are big-endian, the first one is stored at `address - 2`.
+    pub stack_address: Option<usize>,
+    pub memory_access: MemoryAccess,
+    pub machine_code: MachineCode,
+
+    // Backwards-compat flags
+    pub copy_vy_while_shifting: bool,
+    pub increment_index_during_save_load: bool,
+    pub index_overflow_flag: bool,
+    pub reset_vf_on_logic_ops: bool,
+    pub display_wait: bool,
+    pub wrap_sprites: bool,
+    pub jump_with_vx: bool,
+    /// FX0A completes when the key is released instead of when it is pressed
+    pub wait_for_key_release: bool,
+    /// DXY0 draws an 8x16 sprite in low resolution instead of a 16x16 one
+    pub lores_tall_sprites: bool,
+    /// Scrolling moves half as far in low resolution, the HP-48 scrolled its 128x64 screen in both modes
+    pub half_scroll_in_lores: bool,
+}
+impl Default for Chip8Config {
+    fn default() -> Self {
+        Self::modern()
+    }
+}
+
+impl Chip8Config {
+    pub const INSTRUCTIONS_PER_SECOND: usize = 700;
+    pub const PROGRAM_START: usize = 0x200;
+    /// In bytes
+    pub const FONT_CHAR_SIZE: usize = 5;
+    pub const DEFAULT_FONT: [u8; Self::FONT_CHAR_SIZE * 16] = [
+        0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+        0x20, 0x60, 0x20, 0x20, 0x70, // 1
+        0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+        0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+        0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+        0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+        0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+        0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+        0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+        0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+        0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+        0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+        0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+        0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+        0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+        0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+    ];
+    pub const FONT_START: usize = 0x050;
+    /// In bytes
+    pub const BIG_FONT_CHAR_SIZE: usize = 10;
+    pub const DEFAULT_BIG_FONT: [u8; Self::BIG_FONT_CHAR_SIZE * 16] = [
+        0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+        0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+        0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+        0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+        0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+        0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+        0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+        0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+        0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+        0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+        0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+        0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+        0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+        0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+        0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+        0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+    ];
+    pub const BIG_FONT_START: usize = 0x0A0;
+    pub const STACK_DEPTH: usize = 16;
+    /// A quarter of a second, 15 frames
+    pub const MAX_CATCH_UP: u32 = 250_000;
+
+    /// Quirks of the original CHIP-8 interpreter on the RCA COSMAC VIP (1977).
+    pub fn cosmac_vip() -> Self {
+        Self {
+            stack_depth: 12,
+            // The interpreter pushes with STXD from 0xECF, so the first return address is at 0xECE
+            stack_address: Some(0xED0),
+            copy_vy_while_shifting: true,
+            increment_index_during_save_load: true,
+            index_overflow_flag: false,
+            reset_vf_on_logic_ops: true,
+            display_wait: true,
+            wrap_sprites: false,
+            jump_with_vx: false,
+            wait_for_key_release: true,
+            ..Self::modern()
+        }
+    }
+
+    /// The two-page display variant of the COSMAC VIP interpreter (1978).
+    pub fn chip8_hires() -> Self {
+        Self {
+            variant: Variant::Chip8Hires,
+            display_height: 64,
+            ..Self::cosmac_vip()
+        }
+    }
+
+    /// Quirks of CHIP-48 on the HP-48 calculators (1990).
+    pub fn chip48() -> Self {
+        Self {
+            copy_vy_while_shifting: false,
+            // CHIP-48 actually incremented the index register by x instead of x + 1,
+            // no known program relies on that so it is treated like SUPER-CHIP 1.1.
+            increment_index_during_save_load: false,
+            index_overflow_flag: false,
+            reset_vf_on_logic_ops: false,
+            display_wait: false,
+            wrap_sprites: false,
+            jump_with_vx: true,
+            wait_for_key_release: false,
+            ..Self::modern()
+        }
+    }
+
+    /// Quirks of SUPER-CHIP 1.0 on the HP-48 calculators (1991).
+    pub fn schip_1_0() -> Self {
+        Self {
+            variant: Variant::SuperChip,
+            copy_vy_while_shifting: false,
+            // Same as CHIP-48, see `Chip8Config::chip48`
+            increment_index_during_save_load: false,
+            index_overflow_flag: false,
+            reset_vf_on_logic_ops: false,
+            display_wait: false,
+            wrap_sprites: false,
+            jump_with_vx: true,
+            wait_for_key_release: false,
+            lores_tall_sprites: true,
+            ..Self::modern()
+        }
+    }
+
+    /// Quirks of SUPER-CHIP 1.1 on the HP-48 calculators (1991).
+    pub fn schip_1_1() -> Self {
+        Self {
+            variant: Variant::SuperChip,
+            copy_vy_while_shifting: false,
+            increment_index_during_save_load: false,
+            index_overflow_flag: false,
+            reset_vf_on_logic_ops: false,
+            display_wait: false,
+            wrap_sprites: false,
+            jump_with_vx: true,
+            wait_for_key_release: false,
+            half_scroll_in_lores: true,
+            ..Self::modern()
+        }
+    }
+
+    /// Quirks of the DREAM 6800's CHIPOS interpreter, which follows the COSMAC VIP's, with programs at 0x200.
+    /// Only the instructions are modelled, not the machine: CHIPOS's font and memory map aren't reproduced, so
+    /// `Chip8Config::DEFAULT_FONT` is loaded at `Chip8Config::FONT_START` and the stack is kept outside memory.
+    pub fn dream_6800() -> Self {
+        Self {
+            stack_address: None,
+            ..Self::cosmac_vip()
+        }
+    }
+
+    /// Quirks of the ETI-660 interpreter, which follows the COSMAC VIP's, with programs at 0x600 and a 64x48 display.
+    /// Like `Chip8Config::dream_6800`, the interpreter's font and memory map aren't reproduced.
+    pub fn eti_660() -> Self {
+        Self {
+            program_start: 0x600,
+            display_height: 48,
+            stack_address: None,
+            ..Self::cosmac_vip()
+        }
+    }
+
+    /// The CHIP-8X interpreter for the COSMAC VIP with the VP-590 colour board, programs start at 0x300.
+    pub fn chip8x() -> Self {
+        Self {
+            variant: Variant::Chip8X,
+            program_start: 0x300,
+            ..Self::cosmac_vip()
+        }
+    }
+
+    /// The CHIP-8E interpreter for the COSMAC VIP.
+    pub fn chip8e() -> Self {
+        Self {
+            variant: Variant::Chip8E,
+            ..Self::cosmac_vip()
+        }
+    }
+
+    /// Quirks of XO-CHIP as implemented by Octo (2014).
+    pub fn xo_chip() -> Self {
+        Self {
+            variant: Variant::XoChip,
+            memory_size: Chip8::MAX_MEMORY_SIZE,
+            copy_vy_while_shifting: true,
+            increment_index_during_save_load: true,
+            index_overflow_flag: false,
+            reset_vf_on_logic_ops: false,
+            display_wait: false,
+            wrap_sprites: true,
+            jump_with_vx: false,
+            wait_for_key_release: true,
+            ..Self::modern()
+        }
+    }
+
+    /// SUPER-CHIP 1.1 quirks with MegaChip's instructions and 64 KiB of memory.
+    #[cfg(feature = "alloc")]
+    pub fn megachip() -> Self {
+        Self {
+            variant: Variant::MegaChip,
+            memory_size: Chip8::MAX_MEMORY_SIZE,
+            ..Self::schip_1_1()
+        }
+    }
+
+    /// The behaviour most modern interpreters and programs agree on. This is the default config.
+    pub fn modern() -> Self {
+        Self {
+            variant: Variant::Chip8,
+            instructions_per_second: Self::INSTRUCTIONS_PER_SECOND,
+            max_catch_up: Self::MAX_CATCH_UP,
+            memory_size: Chip8::MEMORY_SIZE,
+            display_height: Chip8::HEIGHT,
+            program_start: Self::PROGRAM_START,
+            font: Self::DEFAULT_FONT,
+            font_start: Self::FONT_START,
+            big_font: Self::DEFAULT_BIG_FONT,
+            big_font_start: Self::BIG_FONT_START,
+            stack_depth: Self::STACK_DEPTH,
+            stack_address: None,
+            memory_access: MemoryAccess::Wrap,
+            machine_code: MachineCode::Error,
+            copy_vy_while_shifting: false,
+            increment_index_during_save_load: false,
+            index_overflow_flag: false,
+            reset_vf_on_logic_ops: false,
+            display_wait: false,
+            wrap_sprites: false,
+            jump_with_vx: false,
+            wait_for_key_release: false,
+            lores_tall_sprites: false,
+            half_scroll_in_lores: false,
+        }
+    }
+}
+
+/// Register indices from `x` to `y`, backwards if `x > y`
+fn register_range(x: usize, y: usize) -> impl Iterator<Item = usize> {
+    let (start, end, reverse) = if x <= y { (x, y, false) } else { (y, x, true) };
+    (start..=end).map(move |i| if reverse { end + start - i } else { i })
+}
+
+/// Registers, stack, timers and keys of a `Chip8`, memory is left out and read with `Chip8::memory`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MachineState {
+    pub pc: usize,
+    pub index_reg: u16,
+    pub variable_reg: [u8; 16],
+    /// Return addresses, only the first `stack_len` are in use
+    pub stack: [u16; Chip8::STACK_SIZE / 2],
+    pub stack_len: usize,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub keys: [bool; 16],
+}
+
+pub struct Chip8 {
+    /// Pixels are stored row by row, only the first `width() * height()` pixels are used.
+    /// Every pixel is a bitmask of the bitplanes it is lit on, only XO-CHIP uses the second bitplane.
+    pub framebuffer: [u8; Self::HIRES_WIDTH * Self::HIRES_HEIGHT],
+    /// CHIP-8X foreground colour of every 8x1 pixel zone of the 64x32 display, stored row by row.
+    /// Colours are the VP-590's 3-bit codes: 0 black, 1 red, 2 blue, 3 violet, 4 green, 5 yellow, 6 aqua and 7 white.
+    pub color_map: [u8; Self::COLOR_MAP_SIZE],
+    /// CHIP-8X background colour, see `Chip8::background_color`
+    background_color: u8,
+    hires: bool,
+    /// Bitplanes selected by XO-CHIP's FN01
+    planes: u8,
+    keys: [bool; 16],
+    /// CHIP-8X's second keypad
+    keys_2: [bool; 16],
+    pc: usize,
+    memory: Memory,
+    index_reg: u16,
+    stack: [u16; Self::STACK_SIZE / 2],
+    stack_len: usize,
+    delay_timer: u8,
+    sound_timer: u8,
+    ds_timer: Timer, // delay-sound timer
+    program_timer: Timer,
+    variable_reg: [u8; 16],
+    config: Chip8Config,
+    /// Key events since the last instruction, only kept around while FX0A is waiting
+    key_events: KeyQueue,
+    /// The key FX0A saw being pressed and is waiting to be released
+    pressed_key: Option<u8>,
+    /// Set on every 60 Hz tick of `ds_timer`, cleared after the next instruction
+    vblank: bool,
+    rpl_flags: [u8; 16],
+    exited: bool,
+    /// Set while CHIP-8E's FX4F waits for the delay timer
+    delay_wait: bool,
+    /// Last value CHIP-8E's FX03 wrote to output port 3
+    output_port: u8,
+    /// Value of CHIP-8E's input port 3 and whether it was strobed since FXE3 last read it
+    input_port: u8,
+    input_strobe: bool,
+    audio_pattern: [u8; 16],
+    pitch: u8,
+    #[cfg(feature = "alloc")]
+    mega: megachip::MegaChip,
+}
+
+impl Chip8 {
+    pub const WIDTH: usize = 64;
+    pub const HEIGHT: usize = 32;
+    pub const HIRES_WIDTH: usize = 128;
+    pub const HIRES_HEIGHT: usize = 64;
+    /// Zones in `Chip8::color_map`, the display is 8 zones wide
+    pub const COLOR_MAP_SIZE: usize = Self::WIDTH / 8 * Self::HEIGHT;
+    /// Default memory size in bytes
+    pub const MEMORY_SIZE: usize = 4096;
+    /// In bytes
+    pub const MAX_MEMORY_SIZE: usize = 0x10000;
+    /// In bytes
+    pub const STACK_SIZE: usize = 2048;
+
+    pub fn new(mut config: Chip8Config) -> Self {
+        // Memory always holds both fonts and the first byte of the program, so no instruction can wrap around an
+        // empty memory. `Chip8::load_state` rejects the same configs this clamps.
+        let font_end = config.font_start + config.font.len();
+        let big_font_end = config.big_font_start + config.big_font.len();
+        config.memory_size = config
+            .memory_size
+            .max(font_end)
+            .max(big_font_end)
+            .max(config.program_start + 1)
+            .min(Self::MAX_MEMORY_SIZE);
+        config.display_height = config.display_height.max(1);
+        config.stack_depth = config.stack_depth.min(Self::STACK_SIZE / 2);
+        config.stack_address = config
+            .stack_address
+            .map(|address| address.min(config.memory_size));
+        #[cfg(feature = "alloc")]
+        let mut memory: Memory = alloc::vec![0; config.memory_size].into_boxed_slice();
+        #[cfg(not(feature = "alloc"))]
+        let mut memory: Memory = [0; Self::MAX_MEMORY_SIZE];
+        memory[config.font_start..config.font_start + config.font.len()]
+            .copy_from_slice(&config.font);
+        memory[config.big_font_start..config.big_font_start + config.big_font.len()]
+            .copy_from_slice(&config.big_font);
+        // assert!(memory[0x050] == 0xF0); // Assertion for default config
+        Self {
+            framebuffer: [0; Self::HIRES_WIDTH * Self::HIRES_HEIGHT],
+            color_map: [1; Self::COLOR_MAP_SIZE],
+            background_color: 0,
+            hires: false,
+            planes: 0b01,
+            keys: [false; 16],
+            keys_2: [false; 16],
+            pc: config.program_start,
+            memory,
+            index_reg: 0,
+            stack: [0; Self::STACK_SIZE / 2],
+            stack_len: 0,
+            delay_timer: 0,
+            sound_timer: 0,
+            variable_reg: [0; 16],
+            ds_timer: Timer::with_frequency(60),
+            program_timer: Timer::with_frequency(config.instructions_per_second as u32),
+            config,
+            key_events: KeyQueue::new(),
+            pressed_key: None,
+            vblank: false,
+            rpl_flags: [0; 16],
+            exited: false,
+            delay_wait: false,
+            output_port: 0,
+            input_port: 0,
+            input_strobe: false,
+            audio_pattern: [0; 16],
+            pitch: 64,
+            #[cfg(feature = "alloc")]
+            mega: megachip::MegaChip::new(),
+        }
+    }
+
+    pub fn set_program(&mut self, program: &[u8]) -> Result<(), Chip8Error> {
+        let max_size = self.memory_size().saturating_sub(self.config.program_start);
+        if program.len() > max_size {
+            return Err(Chip8Error::ProgramTooLarge {
+                size: program.len(),
+                max_size,
+            });
+        }
+        self.memory[self.config.program_start..self.config.program_start + program.len()]
+            .copy_from_slice(program);
+        Ok(())
+    }
+    pub fn should_play_sound(&self) -> bool {
+        self.sound_timer > 0
+    }
+
+    /// Width of the framebuffer in the current resolution
+    pub fn width(&self) -> usize {
+        if self.hires {
+            Self::HIRES_WIDTH
+        } else {
+            Self::WIDTH
+        }
+    }
+
+    /// Height of the framebuffer in the current resolution
+    pub fn height(&self) -> usize {
+        if self.hires {
+            Self::HIRES_HEIGHT
+        } else {
+            self.config.display_height.min(Self::HIRES_HEIGHT)
+        }
+    }
+
+    /// The XO-CHIP audio pattern, 128 1-bit samples played while `should_play_sound` is true
+    pub fn audio_pattern(&self) -> &[u8; 16] {
+        &self.audio_pattern
+    }
+
+    /// The XO-CHIP pitch register, the audio pattern is played at `4000 * 2 ^ ((pitch - 64) / 48)` samples per second
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    /// Whether the program stopped itself with the SUPER-CHIP 00FD instruction, `update` does nothing after that
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    /// The CHIP-8X background colour stepped through by 02A0: 0 blue, 1 black, 2 green and 3 red
+    pub fn background_color(&self) -> u8 {
+        self.background_color
+    }
+
+    pub fn press(&mut self, key: u8) {
+        if !self.keys[key as usize] {
+            self.keys[key as usize] = true;
+            self.key_events.push(KeyEvent::Press(key));
+        }
+    }
+
+    pub fn release(&mut self, key: u8) {
+        if self.keys[key as usize] {
+            self.keys[key as usize] = false;
+            self.key_events.push(KeyEvent::Release(key));
+        }
+    }
+
+    /// Last value the program wrote to CHIP-8E's output port 3 with FX03
+    pub fn output_port(&self) -> u8 {
+        self.output_port
+    }
+
+    /// Sets CHIP-8E's input port 3 and strobes it, FXE7 reads the value and FXE3 waits for the strobe
+    pub fn set_input_port(&mut self, value: u8) {
+        self.input_port = value;
+        self.input_strobe = true;
+    }
+
+    /// Presses a key on CHIP-8X's second keypad, only EXF2 and EXF5 read it
+    pub fn press_second_keypad(&mut self, key: u8) {
+        self.keys_2[key as usize] = true;
+    }
+
+    pub fn release_second_keypad(&mut self, key: u8) {
+        self.keys_2[key as usize] = false;
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn set_pc(&mut self, pc: usize) {
+        self.pc = pc;
+    }
+
+    pub fn index_reg(&self) -> u16 {
+        self.index_reg
+    }
+
+    pub fn set_index_reg(&mut self, index_reg: u16) {
+        self.index_reg = index_reg;
+    }
+
+    pub fn variable_reg(&self) -> &[u8; 16] {
+        &self.variable_reg
+    }
+
+    pub fn variable_reg_mut(&mut self) -> &mut [u8; 16] {
+        &mut self.variable_reg
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn set_delay_timer(&mut self, delay_timer: u8) {
+        self.delay_timer = delay_timer;
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub fn set_sound_timer(&mut self, sound_timer: u8) {
+        self.sound_timer = sound_timer;
+    }
+
+    /// `Chip8Config::memory_size` bytes, the stack is in here when `Chip8Config::stack_address` is set
+    pub fn memory(&self) -> &[u8] {
+        &self.memory[..self.memory_size()]
+    }
+
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        let memory_size = self.memory_size();
+        &mut self.memory[..memory_size]
+    }
+
+    /// Which keys of the first keypad are held, change them with `press` and `release`
+    pub fn keys(&self) -> &[bool; 16] {
+        &self.keys
+    }
+
+    pub fn state(&self) -> MachineState {
+        let mut stack = [0; Self::STACK_SIZE / 2];
+        for (i, entry) in stack.iter_mut().enumerate().take(self.stack_len) {
+            *entry = self.peek_stack_entry(i);
+        }
+        MachineState {
+            pc: self.pc,
+            index_reg: self.index_reg,
+            variable_reg: self.variable_reg,
+            stack,
+            stack_len: self.stack_len,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            keys: self.keys,
+        }
+    }
+
+    /// Keys are set without key events, so a waiting FX0A doesn't see them.
+    /// A `stack_len` above `Chip8Config::stack_depth` makes the next call overflow.
+    pub fn set_state(&mut self, state: &MachineState) {
+        self.pc = state.pc;
+        self.index_reg = state.index_reg;
+        self.variable_reg = state.variable_reg;
+        self.stack_len = state.stack_len.min(Self::STACK_SIZE / 2);
+        for i in 0..self.stack_len {
+            self.poke_stack_entry(i, state.stack[i]);
+        }
+        self.delay_timer = state.delay_timer;
+        self.sound_timer = state.sound_timer;
+        self.keys = state.keys;
+    }
+
+    fn memory_size(&self) -> usize {
+        self.config.memory_size.min(Self::MAX_MEMORY_SIZE)
+    }
+
+    /// Applies `Chip8Config::memory_access` to `address`, errors are reported for the instruction at `pc - 2`
+    fn memory_index(&self, address: usize) -> Result<usize, Chip8Error> {
+        if address < self.memory_size() {
+            Ok(address)
+        } else {
+            match self.config.memory_access {
+                MemoryAccess::Wrap => Ok(address % self.memory_size()),
+                MemoryAccess::Error => Err(Chip8Error::MemoryOutOfBounds {
+                    pc: self.pc - 2,
+                    address,
+                }),
+            }
+        }
+    }
+
+    fn read(&self, address: usize) -> Result<u8, Chip8Error> {
+        Ok(self.memory[self.memory_index(address)?])
+    }
+
+    fn write(&mut self, address: usize, value: u8) -> Result<(), Chip8Error> {
+        self.memory[self.memory_index(address)?] = value;
+        Ok(())
+    }
+
+    /// Address of entry `i` of a stack in memory that grows down from `top`, wrapping below 0
+    fn stack_slot(&self, top: usize, i: usize) -> usize {
+        let memory_size = self.memory_size();
+        (top + memory_size - (2 * (i + 1)) % memory_size) % memory_size
+    }
+
+    fn stack_entry(&self, i: usize) -> Result<u16, Chip8Error> {
+        if let Some(top) = self.config.stack_address {
+            let address = self.stack_slot(top, i);
+            Ok(u16::from_be_bytes([
+                self.read(address)?,
+                self.read(address + 1)?,
+            ]))
+        } else {
+            Ok(self.stack[i])
+        }
+    }
+
+    fn set_stack_entry(&mut self, i: usize, value: u16) -> Result<(), Chip8Error> {
+        if let Some(top) = self.config.stack_address {
+            let address = self.stack_slot(top, i);
+            let [high, low] = value.to_be_bytes();
+            self.write(address, high)?;
+            self.write(address + 1, low)
+        } else {
+            self.stack[i] = value;
+            Ok(())
+        }
+    }
+
+    /// Same as `stack_entry`, but wraps around memory instead of failing, for the host
+    fn peek_stack_entry(&self, i: usize) -> u16 {
+        if let Some(top) = self.config.stack_address {
+            let address = self.stack_slot(top, i);
+            let memory_size = self.memory_size();
+            u16::from_be_bytes([
+                self.memory[address],
+                self.memory[(address + 1) % memory_size],
+            ])
+        } else {
+            self.stack[i]
+        }
+    }
+
+    /// Same as `set_stack_entry`, but wraps around memory instead of failing, for the host
+    fn poke_stack_entry(&mut self, i: usize, value: u16) {
+        if let Some(top) = self.config.stack_address {
+            let address = self.stack_slot(top, i);
+            let memory_size = self.memory_size();
+            let [high, low] = value.to_be_bytes();
+            self.memory[address] = high;
+            self.memory[(address + 1) % memory_size] = low;
+        } else {
+            self.stack[i] = value;
+        }
+    }
+
+    /// Skips the next instruction, XO-CHIP's F000 NNNN is two instructions long
+    fn skip(&mut self) -> Result<(), Chip8Error> {
+        if self.config.variant.xo_chip()
+            && self.read(self.pc)? == 0xF0
+            && self.read(self.pc + 1)? == 0x00
+        {
+            self.pc += 4;
+        } else {
+            self.pc += 2;
+        }
+        Ok(())
+    }
+
+    /// Clears the selected bitplanes
+    fn clear(&mut self) {
+        #[cfg(feature = "alloc")]
+        if self.mega.enabled {
+            return self.clear_megachip();
+        }
+        let planes = self.planes;
+        for pixel in self.framebuffer.iter_mut() {
+            *pixel &= !planes;
+        }
+    }
+
+    /// How many pixels a scroll instruction moves the display by
+    fn scroll_distance(&self, pixels: isize) -> isize {
+        if self.config.half_scroll_in_lores && !self.hires {
+            // LEGACY : The HP-48 interpreters scrolled by high resolution pixels, even in low resolution
+            pixels / 2
+        } else {
+            pixels
+        }
+    }
+
+    /// Moves the selected bitplanes by `dx` pixels to the right and `dy` pixels down
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        #[cfg(feature = "alloc")]
+        if self.mega.enabled {
+            return self.scroll_megachip(dx, dy);
+        }
+        let (width, height) = (self.width() as isize, self.height() as isize);
+        let planes = self.planes;
+        // Walk against the scroll so every pixel is moved before it's overwritten
+        for row in 0..height {
+            let y = if dy > 0 { height - 1 - row } else { row };
+            for column in 0..width {
+                let x = if dx > 0 { width - 1 - column } else { column };
+                let (old_x, old_y) = (x - dx, y - dy);
+                let moved = if (0..width).contains(&old_x) && (0..height).contains(&old_y) {
+                    self.framebuffer[(old_y * width + old_x) as usize]
+                } else {
+                    0
+                };
+                let pixel = &mut self.framebuffer[(y * width + x) as usize];
+                *pixel = (*pixel & !planes) | (moved & planes);
+            }
+        }
+    }
+
+    /// Runs every instruction and timer tick due in `delta` microseconds, up to `Chip8Config::max_catch_up`
+    pub fn update(
+        &mut self,
+        delta: u128,
+        random_source: impl FnMut() -> u8,
+    ) -> Result<(), Chip8Error> {
+        self.update_with(delta, random_source, &mut Host::default())
+    }
+
+    /// Same as `Chip8::update`, but instructions can call into `host`
+    pub fn update_with(
+        &mut self,
+        delta: u128,
+        mut random_source: impl FnMut() -> u8,
+        host: &mut Host<'_>,
+    ) -> Result<(), Chip8Error> {
+        let mut delta = u32::try_from(delta)
+            .unwrap_or(u32::MAX)
+            .min(self.config.max_catch_up);
+        // Runs the time up to each frame boundary, so instructions see the timers change when they should
+        while delta > 0 && !self.exited {
+            // Always at least a microsecond, so time moves on even if a tick is somehow overdue
+            let elapsed = delta.min(self.ds_timer.until_tick().max(1));
+            self.program_timer.add(elapsed as u128);
+            while !self.exited && self.program_timer.tick() {
+                if let Err(error) = self.step_inner(&mut random_source, host) {
+                    // Drops the instructions that were still due, a saved state never has a tick overdue
+                    self.program_timer.raw %= self.program_timer.period;
+                    return Err(error);
+                }
+            }
+            self.ds_timer.add(elapsed as u128);
+            if self.ds_timer.tick() {
+                self.tick_timers();
+            }
+            delta -= elapsed;
+        }
+        Ok(())
+    }
+
+    /// Executes exactly one instruction, whatever the time
+    pub fn step(&mut self, random_source: impl FnOnce() -> u8) -> Result<(), Chip8Error> {
+        self.step_inner(random_source, &mut Host::default())
+    }
+
+    /// Same as `Chip8::step`, but the instruction can call into `host`
+    pub fn step_with(
+        &mut self,
+        random_source: impl FnOnce() -> u8,
+        host: &mut Host<'_>,
+    ) -> Result<(), Chip8Error> {
+        self.step_inner(random_source, host)
+    }
+
+    /// Decrements the delay and sound timers once and starts a new frame, hosts calling `Chip8::step`
+    /// should call it 60 times per second
+    pub fn tick_timers(&mut self) {
+        // Frame boundary
+        self.vblank = true;
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+        }
+        if self.sound_timer > 0 {
+            self.sound_timer -= 1;
+        }
+    }
+
+    /// Runs one 60 Hz frame like Octo does, `instructions_per_frame` instructions then a timer tick
+    ///
+    /// Octo calls `instructions_per_frame` the tickrate, `Chip8Config::instructions_per_second / 60` runs at the speed of `Chip8::update`
+    pub fn run_frame(
+        &mut self,
+        instructions_per_frame: usize,
+        random_source: impl FnMut() -> u8,
+    ) -> Result<(), Chip8Error> {
+        self.run_frame_with(instructions_per_frame, random_source, &mut Host::default())
+    }
+
+    /// Same as `Chip8::run_frame`, but instructions can call into `host`
+    pub fn run_frame_with(
+        &mut self,
+        instructions_per_frame: usize,
+        mut random_source: impl FnMut() -> u8,
+        host: &mut Host<'_>,
+    ) -> Result<(), Chip8Error> {
+        for _ in 0..instructions_per_frame {
+            if self.exited {
+                break;
+            }
+            self.step_inner(&mut random_source, host)?;
+        }
+        self.tick_timers();
+        Ok(())
+    }
+
+    fn step_inner(
+        &mut self,
+        random_source: impl FnOnce() -> u8,
+        host: &mut Host<'_>,
+    ) -> Result<(), Chip8Error> {
+        if self.exited {
+            return Ok(());
+        }
+        let vblank = core::mem::take(&mut self.vblank);
+        if self.config.memory_access == MemoryAccess::Wrap {
+            self.pc %= self.memory_size();
+        }
+        self.pc += 2;
+        let opcode = match (self.read(self.pc - 2), self.read(self.pc - 1)) {
+            (Ok(high), Ok(low)) => u16::from_be_bytes([high, low]),
+            (Err(error), _) | (_, Err(error)) => {
+                // Stays on the failed fetch, so pc never runs further off the end of memory
+                self.pc -= 2;
+                return Err(error);
+            }
+        };
+        let instruction = Instruction::decode_for(opcode, self.config.variant)
+            .map_err(|_| Chip8Error::invalid_instruction(self.pc - 2, opcode))?;
+        if !matches!(instruction, Instruction::WaitKey { .. }) {
+            // Only FX0A cares about key events
+            self.key_events.clear();
+            self.pressed_key = None;
+        }
+        self.execute(instruction, vblank, random_source, host)
+    }
+
+    /// Runs an instruction that was fetched from `pc - 2`
+    fn execute(
+        &mut self,
+        instruction: Instruction,
+        vblank: bool,
+        random_source: impl FnOnce() -> u8,
+        host: &mut Host<'_>,
+    ) -> Result<(), Chip8Error> {
+        let pc = self.pc - 2;
+        match instruction {
+            Instruction::Return => {
+                // INST 00EE
+                if self.stack_len == 0 {
+                    return Err(Chip8Error::pop_empty_stack(pc, instruction.encode()));
+                }
+                self.stack_len -= 1;
+                self.pc = self.stack_entry(self.stack_len)? as usize;
+            }
+            Instruction::Clear => {
+                // INST 00E0 : clear
+                self.clear();
+            }
+            Instruction::HiresClear => {
+                // INST 0230 : clear // Clears the 64x64 display
+                self.clear();
+            }
+            Instruction::StepBackground => {
+                // INST 02A0 : step the background colour
+                self.background_color = (self.background_color + 1) % 4;
+            }
+            Instruction::Stop => {
+                // INST 00ED : stop
+                self.exited = true;
+            }
+            Instruction::Nop => {
+                // INST 00F2 : no operation
+            }
+            Instruction::WaitDelay => {
+                // INST 0151 : wait until delay = 0
+                if self.delay_timer > 0 {
+                    self.pc -= 2; // wait
+                }
+            }
+            Instruction::SkipNext => {
+                // INST 0188 : skip the next instruction
+                self.skip()?;
+            }
+            Instruction::ScrollDown { n } => {
+                // INST 00CN : scroll-down N
+                self.scroll(0, self.scroll_distance(n as isize));
+            }
+            Instruction::ScrollUp { n } => {
+                // INST 00DN : scroll-up N
+                self.scroll(0, -self.scroll_distance(n as isize));
+            }
+            Instruction::ScrollRight => {
+                // INST 00FB : scroll-right
+                self.scroll(self.scroll_distance(4), 0);
+            }
+            Instruction::ScrollLeft => {
+                // INST 00FC : scroll-left
+                self.scroll(-self.scroll_distance(4), 0);
+            }
+            Instruction::Exit => {
+                // INST 00FD : exit
+                self.exited = true;
+            }
+            Instruction::Lores | Instruction::Hires => {
+                // INST 00FE : lores
+                // INST 00FF : hires
+                self.hires = instruction == Instruction::Hires;
+                self.framebuffer.fill(0);
+            }
+            #[cfg(feature = "alloc")]
+            Instruction::MegaChip(instruction) => {
+                self.execute_megachip(instruction)?;
+            }
+            Instruction::MachineCode { address } => {
+                // INST 0NNN : Call machine code routine at NNN
+                let memory_size = self.memory_size();
+                match (self.config.machine_code, &mut host.handler) {
+                    (MachineCode::Ignore, _) => (),
+                    (MachineCode::Custom, Some(handler)) => {
+                        handler.call(
+                            address,
+                            Machine {
+                                variable_reg: &mut self.variable_reg,
+                                index_reg: &mut self.index_reg,
+                                memory: &mut self.memory[..memory_size],
+                                framebuffer: &mut self.framebuffer,
+                            },
+                        )?;
+                    }
+                    _ => {
+                        return Err(Chip8Error::invalid_instruction(pc, instruction.encode()));
+                    }
+                }
+            }
+            Instruction::Jump { address } => {
+                // INST 1NNN : jump NNN
+                if self.config.variant == Variant::Chip8Hires
+                    && pc == self.config.program_start
+                    && address == 0x260
+                {
+                    // The patched interpreter at 0x260 would set up the display and start the program at 0x2C0
+                    self.pc = 0x2C0;
+                } else {
+                    self.pc = address as usize;
+                }
+            }
+            Instruction::Call { address } => {
+                // INST 2NNN
+                if self.stack_len >= self.config.stack_depth.min(Self::STACK_SIZE / 2) {
+                    return Err(Chip8Error::stack_overflow(pc, instruction.encode()));
+                }
+                self.set_stack_entry(self.stack_len, self.pc as u16)?;
+                self.stack_len += 1;
+                self.pc = address as usize;
+            }
+            Instruction::SkipIfEqual { x, value } => {
+                // INST 3XNN : if vx != NN then
+                if self.variable_reg[x as usize] == value {
+                    self.skip()?;
+                }
+            }
+            Instruction::SkipIfNotEqual { x, value } => {
+                // INST 4XNN : if vx == NN then
+                if self.variable_reg[x as usize] != value {
+                    self.skip()?;
+                }
+            }
+            Instruction::SkipIfGreater { x, y } => {
+                // INST 5XY1 : if vx <= vy then
+                if self.variable_reg[x as usize] > self.variable_reg[y as usize] {
+                    self.skip()?;
+                }
+            }
+            Instruction::SaveRange { x, y } => {
+                // INST 5XY2 : save vx - vy // Save vx-vy to index_reg, in reverse if x > y
+                for (i, r) in register_range(x as usize, y as usize).enumerate() {
+                    self.write(self.index_reg as usize + i, self.variable_reg[r])?;
+                }
+            }
+            Instruction::LoadRange { x, y } => {
+                // INST 5XY3 : load vx - vy // Load vx-vy from index_reg, in reverse if x > y
+                for (i, r) in register_range(x as usize, y as usize).enumerate() {
+                    self.variable_reg[r] = self.read(self.index_reg as usize + i)?;
+                }
+            }
+            Instruction::AddNibbles { x, y } => {
+                // INST 5XY1 : vx += vy // Each nibble is added on its own, modulo 8
+                let vx = self.variable_reg[x as usize];
+                let vy = self.variable_reg[y as usize];
+                let high = ((vx >> 4) + (vy >> 4)) & 0x7;
+                let low = ((vx & 0xF) + (vy & 0xF)) & 0x7;
+                self.variable_reg[x as usize] = (high << 4) | low;
+            }
+            Instruction::SkipIfRegistersEqual { x, y } => {
+                // INST 5XY0 : if vx != vy then
+                if self.variable_reg[x as usize] == self.variable_reg[y as usize] {
+                    self.skip()?;
+                }
+            }
+            Instruction::Load { x, value } => {
+                // INST 6XNN : vx := NN
+                self.variable_reg[x as usize] = value;
+            }
+            Instruction::Add { x, value } => {
+                // INST 7XNN : vx += NN // Does not touch vf
+                let vx = &mut self.variable_reg[x as usize];
+                *vx = vx.wrapping_add(value);
+            }
+            Instruction::Move { x, y }
+            | Instruction::Or { x, y }
+            | Instruction::And { x, y }
+            | Instruction::Xor { x, y }
+            | Instruction::AddRegisters { x, y }
+            | Instruction::Subtract { x, y }
+            | Instruction::ShiftRight { x, y }
+            | Instruction::SubtractReverse { x, y }
+            | Instruction::ShiftLeft { x, y } => {
+                let vy = self.variable_reg[y as usize];
+                let vx = self.variable_reg[x as usize];
+                // The flag is always written after the result, so vf holds the flag even when x = F
+                // LEGACY : The COSMAC VIP interpreter would clear vf after the logic operations
+                let logic_flag = self.config.reset_vf_on_logic_ops.then_some(0);
+                // LEGACY : Old interpreters would copy vy into vx before shifting
+                let shifted = if self.config.copy_vy_while_shifting {
+                    vy
+                } else {
+                    vx
+                };
+                let (result, flag) = match instruction {
+                    Instruction::Move { .. } => {
+                        // INST 8XY0 : vx := vy
+                        (vy, None)
+                    }
+                    Instruction::Or { .. } => {
+                        // INST 8XY1 : vx |= vy
+                        (vx | vy, logic_flag)
+                    }
+                    Instruction::And { .. } => {
+                        // INST 8XY2 : vx &= vy
+                        (vx & vy, logic_flag)
+                    }
+                    Instruction::Xor { .. } => {
+                        // INST 8XY3 : vx ^= vy
+                        (vx ^ vy, logic_flag)
+                    }
+                    Instruction::AddRegisters { .. } => {
+                        // INST 8XY4 : vx += vy // vf := 1 on carry
+                        let (result, carry) = vx.overflowing_add(vy);
+                        (result, Some(carry as u8))
+                    }
+                    Instruction::Subtract { .. } => {
+                        // INST 8XY5 : vx -= vy // vf := 0 on borrow
+                        (vx.wrapping_sub(vy), Some((vx >= vy) as u8))
+                    }
+                    Instruction::ShiftRight { .. } => {
+                        // INST 8XY6 : vx >>= vy // vf := shifted out bit
+                        (shifted >> 1, Some(shifted & 0b1))
+                    }
+                    Instruction::SubtractReverse { .. } => {
+                        // INST 8XY7 : vx = vy - vx // vf := 0 on borrow
+                        (vy.wrapping_sub(vx), Some((vy >= vx) as u8))
+                    }
+                    _ => {
+                        // INST 8XYE : vx <<= vy // vf := shifted out bit
+                        (shifted << 1, Some(shifted >> 7))
+                    }
+                };
+                self.variable_reg[x as usize] = result;
+                if let Some(flag) = flag {
+                    self.variable_reg[0xF] = flag;
+                }
+            }
+            Instruction::SkipIfRegistersNotEqual { x, y } => {
+                // INST 9XY0 : if vx == vy then
+                if self.variable_reg[x as usize] != self.variable_reg[y as usize] {
+                    self.skip()?;
+                }
+            }
+            Instruction::LoadIndex { address } => {
+                // INST ANNN : index_reg := NNN
+                self.index_reg = address;
+            }
+            Instruction::ColorZones { x, y } => {
+                // INST BXY0 : colour vy on 8x4 zones
+                // The low nibbles of vx and v(x + 1) are the first horizontal and vertical zone,
+                // their high nibbles are how many more zones to colour
+                let vx = self.variable_reg[x as usize];
+                let vx_1 = self.variable_reg[(x as usize + 1) & 0xF];
+                let vy = self.variable_reg[y as usize];
+                let zones = Self::WIDTH / 8;
+                for zone_y in 0..=(vx_1 >> 4) as usize {
+                    let zone_y = ((vx_1 & 0xF) as usize + zone_y) % (Self::HEIGHT / 4);
+                    for zone_x in 0..=(vx >> 4) as usize {
+                        let zone_x = ((vx & 0xF) as usize + zone_x) % zones;
+                        for row in zone_y * 4..zone_y * 4 + 4 {
+                            self.color_map[row * zones + zone_x] = vy & 0x7;
+                        }
+                    }
+                }
+            }
+            Instruction::ColorRows { x, y, n } => {
+                // INST BXYN : colour v(x + 1) on N 8x1 zones // vx and vy are the pixel coordinates of the first zone
+                let vx = self.variable_reg[x as usize];
+                let vx_1 = self.variable_reg[(x as usize + 1) & 0xF];
+                let vy = self.variable_reg[y as usize];
+                let zones = Self::WIDTH / 8;
+                let zone_x = (vx as usize / 8) % zones;
+                for row in 0..n as usize {
+                    let row = (vy as usize + row) % Self::HEIGHT;
+                    self.color_map[row * zones + zone_x] = vx_1 & 0x7;
+                }
+            }
+            Instruction::JumpBack { offset } => {
+                // INST BBNN : jump back NN bytes
+                let memory_size = self.memory_size();
+                self.pc = (self.pc + memory_size - offset as usize % memory_size) % memory_size;
+            }
+            Instruction::JumpForward { offset } => {
+                // INST BFNN : jump forward NN bytes
+                self.pc = (self.pc + offset as usize) % self.memory_size();
+            }
+            Instruction::JumpOffset { address } => {
+                // INST BNNN : jump NNN + v0
+                let offset = if self.config.jump_with_vx {
+                    // LEGACY : CHIP-48 and SUPER-CHIP would read this as BXNN : jump XNN + vx
+                    self.variable_reg[(address >> 8) as usize] as u16
+                } else {
+                    self.variable_reg[0x0] as u16
+                };
+                self.pc = (address + offset) as usize % self.memory_size();
+            }
+            Instruction::Random { x, mask } => {
+                // INST CXNN
+                self.variable_reg[x as usize] = random_source() & mask;
+            }
+            Instruction::Draw { x, y, n } => {
+                // INST DXYN : sprite vx vy N
+                if self.config.display_wait && !vblank {
+                    // LEGACY : The COSMAC VIP interpreter would wait for the next vertical blank before drawing
+                    self.pc -= 2; // wait
+                    return Ok(());
+                }
+                let vx = self.variable_reg[x as usize];
+                let vy = self.variable_reg[y as usize];
+                #[cfg(feature = "alloc")]
+                if self.mega.enabled {
+                    return self.draw_megachip_sprite(vx, vy);
+                }
+                let (width, height) = (self.width(), self.height());
+                let (rows, columns) = if n == 0 && self.config.variant.superchip() {
+                    if self.config.lores_tall_sprites && !self.hires {
+                        // LEGACY : SUPER-CHIP 1.0 would draw an 8x16 sprite in low resolution
+                        (16, 8)
+                    } else {
+                        // INST DXY0 : sprite vx vy 0 // 16x16 sprite, two bytes per row
+                        (16, 16)
+                    }
+                } else {
+                    (n as usize, 8)
+                };
+
+                let x = vx as usize % width;
+                let y = vy as usize % height;
+                self.variable_reg[0xF] = 0;
+                // XO-CHIP draws the sprite data one after the other on each selected bitplane
+                let mut address = self.index_reg as usize;
+                for plane in [0b01, 0b10] {
+                    if self.planes & plane == 0 {
+                        continue;
+                    }
+                    let mut y = y;
+                    for i in 0..rows {
+                        let data = if columns == 16 {
+                            let address = address + i * 2;
+                            u16::from_be_bytes([self.read(address)?, self.read(address + 1)?])
+                        } else {
+                            (self.read(address + i)? as u16) << 8
+                        };
+                        let mut x = x;
+                        for j in 0..columns {
+                            let data_bit = ((0x8000 >> j) & data) > 0;
+                            let current_pixel = &mut self.framebuffer[y * width + x];
+                            if data_bit {
+                                if *current_pixel & plane != 0 {
+                                    self.variable_reg[0xF] = 1;
+                                }
+                                *current_pixel ^= plane;
+                            }
+                            x += 1;
+                            if x >= width {
+                                if self.config.wrap_sprites {
+                                    x = 0;
+                                } else {
+                                    break;
+                                }
+                            }
+                        }
+                        y += 1;
+                        if y >= height {
+                            if self.config.wrap_sprites {
+                                y = 0;
+                            } else {
+                                break;
+                            }
+                        }
+                    }
+                    address += rows * columns / 8;
+                }
+            }
+            Instruction::SkipIfKey { x }
+            | Instruction::SkipIfNotKey { x }
+            | Instruction::SkipIfKey2 { x }
+            | Instruction::SkipIfNotKey2 { x } => {
+                let vx = self.variable_reg[x as usize];
+                if vx < self.keys.len() as u8 {
+                    let skip = match instruction {
+                        // INST EX9E : if key = vx not pressed then
+                        Instruction::SkipIfKey { .. } => self.keys[vx as usize],
+                        // INST EXA1 : if key = vx is pressed then
+                        Instruction::SkipIfNotKey { .. } => !self.keys[vx as usize],
+                        // INST EXF2 : if key = vx not pressed on keypad 2 then
+                        Instruction::SkipIfKey2 { .. } => self.keys_2[vx as usize],
+                        // INST EXF5 : if key = vx is pressed on keypad 2 then
+                        _ => !self.keys_2[vx as usize],
+                    };
+                    if skip {
+                        self.skip()?;
+                    }
+                } else {
+                    // eprintln!("[WARN] Tried to check whether key {vx} is pressed but max value of key is {}.", self.keys.len())
+                }
+            }
+            Instruction::LongIndex => {
+                // INST F000 NNNN : index_reg := long NNNN
+                self.index_reg = u16::from_be_bytes([self.read(self.pc)?, self.read(self.pc + 1)?]);
+                self.pc += 2;
+            }
+            Instruction::Plane { n } => {
+                // INST FN01 : plane N
+                self.planes = n & 0b11;
+            }
+            Instruction::Audio => {
+                // INST F002 : audio // Load 16 bytes from index_reg into the audio pattern
+                for i in 0..self.audio_pattern.len() {
+                    self.audio_pattern[i] = self.read(self.index_reg as usize + i)?;
+                }
+            }
+            Instruction::Output { x } => {
+                // INST FX03 : output vx to port 3
+                self.output_port = self.variable_reg[x as usize];
+            }
+            Instruction::LoadDelay { x } => {
+                // INST FX07 : vx := delay
+                self.variable_reg[x as usize] = self.delay_timer;
+            }
+            Instruction::WaitKey { x } => {
+                // INST FX0A : vx := key // Wait for a keypress
+                let mut key = None;
+                while let Some(event) = self.key_events.pop() {
+                    match event {
+                        KeyEvent::Press(k) if !self.config.wait_for_key_release => {
+                            key = Some(k);
+                            break;
+                        }
+                        KeyEvent::Press(k) => {
+                            // LEGACY : The COSMAC VIP interpreter would wait for the key to be released
+                            self.pressed_key.get_or_insert(k);
+                        }
+                        KeyEvent::Release(k) if self.pressed_key == Some(k) => {
+                            key = Some(k);
+                            break;
+                        }
+                        KeyEvent::Release(_) => (),
+                    }
+                }
+                if let Some(key) = key {
+                    self.variable_reg[x as usize] = key;
+                    self.pressed_key = None;
+                } else {
+                    self.pc -= 2; // wait
+                }
+            }
+            Instruction::SetDelay { x } => {
+                // INST FX15 : delay := vx
+                self.delay_timer = self.variable_reg[x as usize];
+            }
+            Instruction::SetSound { x } => {
+                // INST FX18 : sound := vx
+                self.sound_timer = self.variable_reg[x as usize];
+            }
+            Instruction::SkipBytes { x } => {
+                // INST FX1B : skip vx bytes
+                self.pc += self.variable_reg[x as usize] as usize;
+            }
+            Instruction::AddIndex { x } => {
+                // INST FX1E : index_reg += vx
+                self.index_reg = self
+                    .index_reg
+                    .wrapping_add(self.variable_reg[x as usize] as u16);
+                if self.config.index_overflow_flag {
+                    // LEGACY : The interpreter for Amiga would treat index_reg going above 0x0FFF as a special overflow and would set vf := 1 in that case
+                    // The game called "Spacefight 2091!" relies on this.
+                    if self.index_reg > 0x0FFF {
+                        self.variable_reg[0xF] = 1;
+                    }
+                }
+            }
+            Instruction::Pitch { x } => {
+                // INST FX3A : pitch := vx
+                self.pitch = self.variable_reg[x as usize];
+            }
+            Instruction::Delay { x } => {
+                // INST FX4F : delay := vx and wait until delay = 0
+                if !self.delay_wait {
+                    self.delay_timer = self.variable_reg[x as usize];
+                    self.delay_wait = true;
+                }
+                if self.delay_timer > 0 {
+                    self.pc -= 2; // wait
+                } else {
+                    self.delay_wait = false;
+                }
+            }
+            Instruction::WaitInput { x } => {
+                // INST FXE3 : wait for port 3 to be strobed, then vx := port 3
+                if self.input_strobe {
+                    self.variable_reg[x as usize] = self.input_port;
+                    self.input_strobe = false;
+                } else {
+                    self.pc -= 2; // wait
+                }
+            }
+            Instruction::Input { x } => {
+                // INST FXE7 : vx := port 3
+                self.variable_reg[x as usize] = self.input_port;
+            }
+            Instruction::LoadFont { x } => {
+                // INST FX29 : index_reg := hex vx
+                let ch = (self.variable_reg[x as usize] & 0b00001111) as u16;
+                self.index_reg =
+                    self.config.font_start as u16 + ch * Chip8Config::FONT_CHAR_SIZE as u16;
+            }
+            Instruction::LoadBigFont { x } => {
+                // INST FX30 : index_reg := bighex vx
+                let ch = (self.variable_reg[x as usize] & 0b00001111) as u16;
+                self.index_reg =
+                    self.config.big_font_start as u16 + ch * Chip8Config::BIG_FONT_CHAR_SIZE as u16;
+            }
+            Instruction::Bcd { x } => {
+                // INST FX33 : bcd vx // Decode vx into binary-coded decimal
+                let vx = self.variable_reg[x as usize];
+                self.write(self.index_reg as usize, vx / 100)?;
+                self.write(self.index_reg as usize + 1, (vx / 10) % 10)?;
+                self.write(self.index_reg as usize + 2, (vx % 100) % 10)?;
+            }
+            Instruction::SaveRegisters { x } => {
+                // INST FX55 : save vx // Save v0-vx to index_reg through (index_reg+x)
+                for x in 0..=x as usize {
+                    if self.config.increment_index_during_save_load {
+                        // LEGACY : Old interpreters used to increment the index register along the way.
+                        self.write(self.index_reg as usize, self.variable_reg[x])?;
+                        self.index_reg = self.index_reg.wrapping_add(1);
+                    } else {
+                        self.write(self.index_reg as usize + x, self.variable_reg[x])?;
+                    }
+                }
+            }
+            Instruction::LoadRegisters { x } => {
+                // INST FX65 : load vx // Load v0-vx from index_reg through (index_reg+x)
+                for x in 0..=x as usize {
+                    if self.config.increment_index_during_save_load {
+                        // LEGACY : Old interpreters used to increment the index register along the way.
+                        self.variable_reg[x] = self.read(self.index_reg as usize)?;
+                        self.index_reg = self.index_reg.wrapping_add(1);
+                    } else {
+                        self.variable_reg[x] = self.read(self.index_reg as usize + x)?;
+                    }
+                }
+            }
+            Instruction::SaveFlags { x } => {
+                // INST FX75 : saveflags vx // Save v0-vx to the RPL user flags
+                let x = x as usize;
+                self.rpl_flags[..=x].copy_from_slice(&self.variable_reg[..=x]);
+                if let Some(storage) = &mut host.storage {
+                    storage.save(&self.rpl_flags)?;
+                }
+            }
+            Instruction::LoadFlags { x } => {
+                // INST FX85 : loadflags vx // Load v0-vx from the RPL user flags
+                let x = x as usize;
+                if let Some(storage) = &mut host.storage {
+                    storage.load(&mut self.rpl_flags)?;
+                }
+                self.variable_reg[..=x].copy_from_slice(&self.rpl_flags[..=x]);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,370 @@
+//! MegaChip mode, a 256x192 colour display with palette indexed sprites and digitised sound.
+//! The buffers are allocated when a program turns the mode on with `0011`.
+
+use alloc::boxed::Box;
+use alloc::vec;
+
+use crate::state::{Reader, Writer};
+use crate::{Chip8, Chip8Error, MegaChipInstruction};
+
+/// A digitised sound started by `060N`
+struct Sample {
+    rate: u16,
+    /// Address of the first byte of sample data
+    start: usize,
+    length: usize,
+    position: usize,
+    looping: bool,
+}
+
+pub(crate) struct MegaChip {
+    pub(crate) enabled: bool,
+    /// ARGB colours, loaded by `02NN`. Index 0 is transparent when drawing sprites.
+    palette: [u32; 256],
+    sprite_width: usize,
+    sprite_height: usize,
+    screen_alpha: u8,
+    blend_mode: u8,
+    collision_color: u8,
+    /// The last frame presented by `00E0`, in ARGB
+    framebuffer: Box<[u32]>,
+    /// The frame being drawn, in ARGB
+    back_buffer: Box<[u32]>,
+    /// Palette index of every pixel of `back_buffer`, used for collisions
+    indices: Box<[u8]>,
+    sample: Option<Sample>,
+}
+
+impl MegaChip {
+    pub(crate) fn new() -> Self {
+        Self {
+            enabled: false,
+            palette: [0xFFFFFFFF; 256],
+            sprite_width: 0,
+            sprite_height: 0,
+            screen_alpha: 0xFF,
+            blend_mode: 0,
+            collision_color: 0,
+            framebuffer: Box::default(),
+            back_buffer: Box::default(),
+            indices: Box::default(),
+            sample: None,
+        }
+    }
+
+    /// Blends `source` on top of `destination` with the mode set by `080N`, then fades the result
+    /// in over `destination` by the alpha of `source`
+    fn blend(&self, destination: u32, source: u32) -> u32 {
+        let alpha = source >> 24;
+        let channel = |shift: u32| {
+            let d = (destination >> shift) & 0xFF;
+            let s = (source >> shift) & 0xFF;
+            let c = match self.blend_mode {
+                1 => (3 * d + s) / 4,
+                2 => (d + s) / 2,
+                3 => (d + 3 * s) / 4,
+                4 => (d + s).min(0xFF),
+                5 => d * s / 0xFF,
+                _ => s,
+            };
+            ((d * (0xFF - alpha) + c * alpha) / 0xFF) << shift
+        };
+        0xFF000000 | channel(16) | channel(8) | channel(0)
+    }
+}
+
+impl Chip8 {
+    pub const MEGA_WIDTH: usize = 256;
+    pub const MEGA_HEIGHT: usize = 192;
+
+    /// Whether the program has turned MegaChip mode on with `0011`
+    pub fn is_megachip(&self) -> bool {
+        self.mega.enabled
+    }
+
+    /// The last frame the program presented with `00E0`, `MEGA_WIDTH * MEGA_HEIGHT` ARGB pixels stored row by row.
+    /// Returns `None` if MegaChip mode was never turned on.
+    pub fn megachip_framebuffer(&self) -> Option<&[u32]> {
+        if self.mega.framebuffer.is_empty() {
+            None
+        } else {
+            Some(&self.mega.framebuffer)
+        }
+    }
+
+    /// Alpha the whole screen should be drawn with, set by `05NN`
+    pub fn megachip_screen_alpha(&self) -> u8 {
+        self.mega.screen_alpha
+    }
+
+    /// Samples per second of the digitised sound that is playing, if any
+    pub fn megachip_sample_rate(&self) -> Option<u16> {
+        self.mega.sample.as_ref().map(|sample| sample.rate)
+    }
+
+    /// Fills `out` with unsigned 8-bit samples of the digitised sound that is playing and
+    /// returns how many were written. Fewer than `out.len()` are written once a sound that doesn't loop ends.
+    pub fn drain_megachip_samples(&mut self, out: &mut [u8]) -> usize {
+        let memory_size = self.memory_size();
+        let Some(sample) = &mut self.mega.sample else {
+            return 0;
+        };
+        let mut written = 0;
+        while written < out.len() && sample.length > 0 {
+            if sample.position >= sample.length {
+                if !sample.looping {
+                    break;
+                }
+                sample.position = 0;
+            }
+            out[written] = self.memory[(sample.start + sample.position) % memory_size];
+            sample.position += 1;
+            written += 1;
+        }
+        if !sample.looping && sample.position >= sample.length {
+            self.mega.sample = None;
+        }
+        written
+    }
+
+    pub(crate) fn write_megachip_state(&self, w: &mut Writer<'_>) {
+        let mega = &self.mega;
+        w.bool(mega.enabled);
+        for &color in mega.palette.iter() {
+            w.u32(color);
+        }
+        w.u16(mega.sprite_width as u16);
+        w.u16(mega.sprite_height as u16);
+        w.u8(mega.screen_alpha);
+        w.u8(mega.blend_mode);
+        w.u8(mega.collision_color);
+        // The buffers only exist once the mode was turned on
+        w.bool(!mega.back_buffer.is_empty());
+        for &pixel in mega.framebuffer.iter().chain(mega.back_buffer.iter()) {
+            w.u32(pixel);
+        }
+        w.bytes(&mega.indices);
+        w.bool(mega.sample.is_some());
+        if let Some(sample) = &mega.sample {
+            w.u16(sample.rate);
+            w.usize(sample.start);
+            w.usize(sample.length);
+            w.usize(sample.position);
+            w.bool(sample.looping);
+        }
+    }
+
+    pub(crate) fn read_megachip_state(&mut self, r: &mut Reader<'_>) -> Result<(), Chip8Error> {
+        let enabled = r.bool()?;
+        let mut palette = [0; 256];
+        for color in palette.iter_mut() {
+            *color = r.u32()?;
+        }
+        let sprite_width = r.u16()? as usize;
+        let sprite_height = r.u16()? as usize;
+        if sprite_width > 256 || sprite_height > 256 {
+            return Err(Chip8Error::InvalidState);
+        }
+        self.mega.palette = palette;
+        self.mega.sprite_width = sprite_width;
+        self.mega.sprite_height = sprite_height;
+        self.mega.screen_alpha = r.u8()?;
+        self.mega.blend_mode = r.u8()?;
+        self.mega.collision_color = r.u8()?;
+        if r.bool()? {
+            self.set_megachip(true);
+            for pixel in self.mega.framebuffer.iter_mut() {
+                *pixel = r.u32()?;
+            }
+            for pixel in self.mega.back_buffer.iter_mut() {
+                *pixel = r.u32()?;
+            }
+            let size = self.mega.indices.len();
+            self.mega.indices.copy_from_slice(r.bytes(size)?);
+        } else if enabled {
+            return Err(Chip8Error::InvalidState);
+        }
+        self.mega.enabled = enabled;
+        self.mega.sample = if r.bool()? {
+            Some(Sample {
+                rate: r.u16()?,
+                start: r.usize_max(Self::MAX_MEMORY_SIZE)?,
+                length: r.usize()?,
+                position: r.usize()?,
+                looping: r.bool()?,
+            })
+        } else {
+            None
+        };
+        Ok(())
+    }
+
+    fn set_megachip(&mut self, enabled: bool) {
+        self.mega.enabled = enabled;
+        if enabled {
+            let size = Self::MEGA_WIDTH * Self::MEGA_HEIGHT;
+            if self.mega.back_buffer.is_empty() {
+                self.mega.framebuffer = vec![0; size].into_boxed_slice();
+                self.mega.back_buffer = vec![0; size].into_boxed_slice();
+                self.mega.indices = vec![0; size].into_boxed_slice();
+            } else {
+                self.mega.framebuffer.fill(0);
+                self.mega.back_buffer.fill(0);
+                self.mega.indices.fill(0);
+            }
+        }
+    }
+
+    /// Runs one of the MegaChip specific `0NNN` instructions
+    pub(crate) fn execute_megachip(
+        &mut self,
+        instruction: MegaChipInstruction,
+    ) -> Result<(), Chip8Error> {
+        match instruction {
+            MegaChipInstruction::MegaOff | MegaChipInstruction::MegaOn => {
+                // INST 0010 : megaoff
+                // INST 0011 : megaon
+                self.set_megachip(instruction == MegaChipInstruction::MegaOn);
+            }
+            MegaChipInstruction::ScrollUp { n } => {
+                // INST 00BN : scroll-up N
+                self.scroll(0, -(n as isize));
+            }
+            MegaChipInstruction::LongIndex { high } => {
+                // INST 01NN NNNN : index_reg := long NNNNNN
+                let address =
+                    u32::from_be_bytes([0, high, self.read(self.pc)?, self.read(self.pc + 1)?])
+                        as usize;
+                // index_reg is 16 bits, so addresses past the end of memory can't wrap or be reached
+                if address >= self.memory_size() {
+                    return Err(Chip8Error::MemoryOutOfBounds {
+                        pc: self.pc - 2,
+                        address,
+                    });
+                }
+                self.index_reg = address as u16;
+                self.pc += 2;
+            }
+            MegaChipInstruction::LoadPalette { count } => {
+                // INST 02NN : load NN palette colours from index_reg
+                let address = self.index_reg as usize;
+                for i in 0..count as usize {
+                    let address = address + i * 4;
+                    self.mega.palette[i + 1] = u32::from_be_bytes([
+                        self.read(address)?,
+                        self.read(address + 1)?,
+                        self.read(address + 2)?,
+                        self.read(address + 3)?,
+                    ]);
+                }
+            }
+            MegaChipInstruction::SpriteWidth { width } => {
+                // INST 03NN : sprite width NN // 0 is 256
+                self.mega.sprite_width = if width == 0 { 256 } else { width as usize };
+            }
+            MegaChipInstruction::SpriteHeight { height } => {
+                // INST 04NN : sprite height NN // 0 is 256
+                self.mega.sprite_height = if height == 0 { 256 } else { height as usize };
+            }
+            MegaChipInstruction::ScreenAlpha { alpha } => {
+                // INST 05NN : screen alpha NN
+                self.mega.screen_alpha = alpha;
+            }
+            MegaChipInstruction::PlaySound { n } => {
+                // INST 060N : play the digitised sound at index_reg // Loops if N is 0
+                // The header is a 16-bit sample rate and a 24-bit length, the data starts after 6 bytes
+                let address = self.index_reg as usize;
+                let rate = u16::from_be_bytes([self.read(address)?, self.read(address + 1)?]);
+                let length = u32::from_be_bytes([
+                    0,
+                    self.read(address + 2)?,
+                    self.read(address + 3)?,
+                    self.read(address + 4)?,
+                ]);
+                self.mega.sample = Some(Sample {
+                    rate,
+                    start: self.memory_index(address + 6)?,
+                    length: length as usize,
+                    position: 0,
+                    looping: n == 0x0,
+                });
+            }
+            MegaChipInstruction::StopSound => {
+                // INST 0700 : stop the digitised sound
+                self.mega.sample = None;
+            }
+            MegaChipInstruction::BlendMode { mode } => {
+                // INST 080N : blend mode N
+                self.mega.blend_mode = mode;
+            }
+            MegaChipInstruction::CollisionColor { color } => {
+                // INST 09NN : collision colour NN
+                self.mega.collision_color = color;
+            }
+        }
+        Ok(())
+    }
+
+    /// Presents the back buffer and clears it, MegaChip's `00E0`
+    pub(crate) fn clear_megachip(&mut self) {
+        self.mega
+            .framebuffer
+            .copy_from_slice(&self.mega.back_buffer);
+        self.mega.back_buffer.fill(0);
+        self.mega.indices.fill(0);
+    }
+
+    /// Moves the back buffer by `dx` pixels to the right and `dy` pixels down
+    pub(crate) fn scroll_megachip(&mut self, dx: isize, dy: isize) {
+        let (width, height) = (Self::MEGA_WIDTH as isize, Self::MEGA_HEIGHT as isize);
+        // Walk against the scroll so every pixel is moved before it's overwritten
+        for row in 0..height {
+            let y = if dy > 0 { height - 1 - row } else { row };
+            for column in 0..width {
+                let x = if dx > 0 { width - 1 - column } else { column };
+                let (old_x, old_y) = (x - dx, y - dy);
+                let i = (y * width + x) as usize;
+                if (0..width).contains(&old_x) && (0..height).contains(&old_y) {
+                    let old = (old_y * width + old_x) as usize;
+                    self.mega.back_buffer[i] = self.mega.back_buffer[old];
+                    self.mega.indices[i] = self.mega.indices[old];
+                } else {
+                    self.mega.back_buffer[i] = 0;
+                    self.mega.indices[i] = 0;
+                }
+            }
+        }
+    }
+
+    /// MegaChip's `DXYN`, draws a `03NN` by `04NN` sprite of palette indices from index_reg.
+    /// Sprites are clipped at the edges of the screen.
+    pub(crate) fn draw_megachip_sprite(&mut self, vx: u8, vy: u8) -> Result<(), Chip8Error> {
+        self.variable_reg[0xF] = 0;
+        let address = self.index_reg as usize;
+        for row in 0..self.mega.sprite_height {
+            let y = vy as usize + row;
+            if y >= Self::MEGA_HEIGHT {
+                break;
+            }
+            for column in 0..self.mega.sprite_width {
+                let x = vx as usize + column;
+                if x >= Self::MEGA_WIDTH {
+                    break;
+                }
+                let index = self.read(address + row * self.mega.sprite_width + column)?;
+                if index == 0 {
+                    continue;
+                }
+                let i = y * Self::MEGA_WIDTH + x;
+                if self.mega.indices[i] != 0 && self.mega.indices[i] == self.mega.collision_color {
+                    self.variable_reg[0xF] = 1;
+                }
+                self.mega.indices[i] = index;
+                self.mega.back_buffer[i] = self
+                    .mega
+                    .blend(self.mega.back_buffer[i], self.mega.palette[index as usize]);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,427 @@
+//! Save states, a versioned binary snapshot of a whole `Chip8` that doesn't need `alloc`
+//!
+//! Every number is big-endian and addresses and sizes are stored as 32 bits. The framebuffer keeps the two
+//! bitplane bits of every pixel, packed four pixels to a byte.
+
+use crate::{
+    Chip8, Chip8Config, Chip8Error, KeyEvent, KeyQueue, MachineCode, MemoryAccess, Timer, Variant,
+};
+
+const MAGIC: [u8; 4] = *b"C8SS";
+const VERSION: u8 = 1;
+
+/// Writes into a buffer that may be too small, but keeps counting so the needed size is known
+pub(crate) struct Writer<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl Writer<'_> {
+    pub(crate) fn bytes(&mut self, data: &[u8]) {
+        if let Some(destination) = self.buf.get_mut(self.len..self.len + data.len()) {
+            destination.copy_from_slice(data);
+        }
+        self.len += data.len();
+    }
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.bytes(&[value]);
+    }
+    pub(crate) fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+    pub(crate) fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_be_bytes());
+    }
+    pub(crate) fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_be_bytes());
+    }
+    pub(crate) fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_be_bytes());
+    }
+    pub(crate) fn usize(&mut self, value: usize) {
+        self.u32(value as u32);
+    }
+}
+
+/// Reads a save state, running out of data or finding a value that can't be right is `Chip8Error::InvalidState`
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl Reader<'_> {
+    pub(crate) fn bytes(&mut self, len: usize) -> Result<&[u8], Chip8Error> {
+        if len > self.data.len() {
+            return Err(Chip8Error::InvalidState);
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+    pub(crate) fn array<const N: usize>(&mut self) -> Result<[u8; N], Chip8Error> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.bytes(N)?);
+        Ok(array)
+    }
+    pub(crate) fn u8(&mut self) -> Result<u8, Chip8Error> {
+        Ok(self.array::<1>()?[0])
+    }
+    pub(crate) fn bool(&mut self) -> Result<bool, Chip8Error> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(Chip8Error::InvalidState),
+        }
+    }
+    pub(crate) fn u16(&mut self) -> Result<u16, Chip8Error> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+    pub(crate) fn u32(&mut self) -> Result<u32, Chip8Error> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+    pub(crate) fn u64(&mut self) -> Result<u64, Chip8Error> {
+        Ok(u64::from_be_bytes(self.array()?))
+    }
+    pub(crate) fn usize(&mut self) -> Result<usize, Chip8Error> {
+        Ok(self.u32()? as usize)
+    }
+    /// A value that has to be at most `max`
+    pub(crate) fn u8_max(&mut self, max: u8) -> Result<u8, Chip8Error> {
+        let value = self.u8()?;
+        if value > max {
+            return Err(Chip8Error::InvalidState);
+        }
+        Ok(value)
+    }
+    pub(crate) fn usize_max(&mut self, max: usize) -> Result<usize, Chip8Error> {
+        let value = self.usize()?;
+        if value > max {
+            return Err(Chip8Error::InvalidState);
+        }
+        Ok(value)
+    }
+}
+
+fn write_config(w: &mut Writer<'_>, config: &Chip8Config) {
+    w.u8(match config.variant {
+        Variant::Chip8 => 0,
+        Variant::Chip8Hires => 1,
+        Variant::SuperChip => 2,
+        Variant::XoChip => 3,
+        Variant::Chip8X => 4,
+        Variant::Chip8E => 5,
+        #[cfg(feature = "alloc")]
+        Variant::MegaChip => 6,
+    });
+    w.usize(config.instructions_per_second);
+    w.u32(config.max_catch_up);
+    w.usize(config.memory_size);
+    w.usize(config.display_height);
+    w.usize(config.program_start);
+    w.bytes(&config.font);
+    w.usize(config.font_start);
+    w.bytes(&config.big_font);
+    w.usize(config.big_font_start);
+    w.usize(config.stack_depth);
+    w.bool(config.stack_address.is_some());
+    w.usize(config.stack_address.unwrap_or(0));
+    w.u8(match config.memory_access {
+        MemoryAccess::Wrap => 0,
+        MemoryAccess::Error => 1,
+    });
+    w.u8(match config.machine_code {
+        MachineCode::Ignore => 0,
+        MachineCode::Error => 1,
+        MachineCode::Custom => 2,
+    });
+    let quirks = [
+        config.copy_vy_while_shifting,
+        config.increment_index_during_save_load,
+        config.index_overflow_flag,
+        config.reset_vf_on_logic_ops,
+        config.display_wait,
+        config.wrap_sprites,
+        config.jump_with_vx,
+        config.wait_for_key_release,
+        config.lores_tall_sprites,
+        config.half_scroll_in_lores,
+    ];
+    w.u16(
+        quirks
+            .iter()
+            .enumerate()
+            .fold(0, |bits, (i, &quirk)| bits | (quirk as u16) << i),
+    );
+}
+
+fn read_config(r: &mut Reader<'_>) -> Result<Chip8Config, Chip8Error> {
+    let variant = match r.u8()? {
+        0 => Variant::Chip8,
+        1 => Variant::Chip8Hires,
+        2 => Variant::SuperChip,
+        3 => Variant::XoChip,
+        4 => Variant::Chip8X,
+        5 => Variant::Chip8E,
+        #[cfg(feature = "alloc")]
+        6 => Variant::MegaChip,
+        _ => return Err(Chip8Error::InvalidState),
+    };
+    let instructions_per_second = r.usize()?;
+    let max_catch_up = r.u32()?;
+    let memory_size = r.usize_max(Chip8::MAX_MEMORY_SIZE)?;
+    let display_height = r.usize()?;
+    let program_start = r.usize_max(Chip8::MAX_MEMORY_SIZE)?;
+    let font = r.array()?;
+    // `Chip8::new` copies the fonts into memory
+    let font_start = r.usize_max(Chip8::MAX_MEMORY_SIZE - Chip8Config::FONT_CHAR_SIZE * 16)?;
+    let big_font = r.array()?;
+    let big_font_start =
+        r.usize_max(Chip8::MAX_MEMORY_SIZE - Chip8Config::BIG_FONT_CHAR_SIZE * 16)?;
+    let stack_depth = r.usize()?;
+    let has_stack_address = r.bool()?;
+    let stack_address = r.usize()?;
+    let memory_access = match r.u8()? {
+        0 => MemoryAccess::Wrap,
+        1 => MemoryAccess::Error,
+        _ => return Err(Chip8Error::InvalidState),
+    };
+    let machine_code = match r.u8()? {
+        0 => MachineCode::Ignore,
+        1 => MachineCode::Error,
+        2 => MachineCode::Custom,
+        _ => return Err(Chip8Error::InvalidState),
+    };
+    let quirks = r.u16()?;
+    let quirk = |i: u16| quirks & (1 << i) != 0;
+    // Memory has to hold the program and both fonts, and every instruction wraps or indexes within it
+    let memory_end = [
+        program_start + 1,
+        font_start + font.len(),
+        big_font_start + big_font.len(),
+    ];
+    if memory_end.iter().any(|&end| end > memory_size)
+        || display_height == 0
+        || stack_depth > Chip8::STACK_SIZE / 2
+        || (has_stack_address && stack_address > memory_size)
+    {
+        return Err(Chip8Error::InvalidState);
+    }
+    Ok(Chip8Config {
+        variant,
+        instructions_per_second,
+        max_catch_up,
+        memory_size,
+        display_height,
+        program_start,
+        font,
+        font_start,
+        big_font,
+        big_font_start,
+        stack_depth,
+        stack_address: has_stack_address.then_some(stack_address),
+        memory_access,
+        machine_code,
+        copy_vy_while_shifting: quirk(0),
+        increment_index_during_save_load: quirk(1),
+        index_overflow_flag: quirk(2),
+        reset_vf_on_logic_ops: quirk(3),
+        display_wait: quirk(4),
+        wrap_sprites: quirk(5),
+        jump_with_vx: quirk(6),
+        wait_for_key_release: quirk(7),
+        lores_tall_sprites: quirk(8),
+        half_scroll_in_lores: quirk(9),
+    })
+}
+
+/// Only the elapsed time is saved, the frequencies come from the config
+fn write_timer(w: &mut Writer<'_>, timer: &Timer) {
+    w.u64(timer.raw);
+}
+
+fn read_timer(r: &mut Reader<'_>, timer: &mut Timer) -> Result<(), Chip8Error> {
+    let raw = r.u64()?;
+    // A tick that is due is always taken right away
+    if raw >= timer.period {
+        return Err(Chip8Error::InvalidState);
+    }
+    timer.raw = raw;
+    Ok(())
+}
+
+fn write_keys(w: &mut Writer<'_>, keys: &[bool; 16]) {
+    w.u16(
+        keys.iter()
+            .enumerate()
+            .fold(0, |bits, (i, &key)| bits | (key as u16) << i),
+    );
+}
+
+fn read_keys(r: &mut Reader<'_>) -> Result<[bool; 16], Chip8Error> {
+    let bits = r.u16()?;
+    Ok(core::array::from_fn(|i| bits & (1 << i) != 0))
+}
+
+impl Chip8 {
+    /// Writes a save state of the whole machine into `buf` and returns its size in bytes.
+    /// Fails with `Chip8Error::StateBufferTooSmall` if it doesn't fit, `state_size` tells how big it is.
+    pub fn save_state(&self, buf: &mut [u8]) -> Result<usize, Chip8Error> {
+        let mut w = Writer { buf, len: 0 };
+        self.write_state(&mut w);
+        if w.len > w.buf.len() {
+            return Err(Chip8Error::StateBufferTooSmall {
+                size: w.buf.len(),
+                needed: w.len,
+            });
+        }
+        Ok(w.len)
+    }
+
+    /// Size in bytes of the save state `save_state` would write right now
+    pub fn state_size(&self) -> usize {
+        let mut w = Writer {
+            buf: &mut [],
+            len: 0,
+        };
+        self.write_state(&mut w);
+        w.len
+    }
+
+    /// Restores a machine from a save state written by `save_state`, with the config it was saved with
+    pub fn load_state(state: &[u8]) -> Result<Self, Chip8Error> {
+        let mut r = Reader { data: state };
+        if r.array()? != MAGIC {
+            return Err(Chip8Error::InvalidState);
+        }
+        let version = r.u8()?;
+        if version != VERSION {
+            return Err(Chip8Error::UnsupportedStateVersion { version });
+        }
+        let mut chip8 = Chip8::new(read_config(&mut r)?);
+        chip8.read_state(&mut r)?;
+        if !r.data.is_empty() {
+            return Err(Chip8Error::InvalidState);
+        }
+        Ok(chip8)
+    }
+
+    fn write_state(&self, w: &mut Writer<'_>) {
+        w.bytes(&MAGIC);
+        w.u8(VERSION);
+        write_config(w, &self.config);
+
+        w.bytes(&self.memory[..self.memory_size()]);
+        w.usize(self.pc);
+        w.u16(self.index_reg);
+        w.bytes(&self.variable_reg);
+        w.usize(self.stack_len);
+        if self.config.stack_address.is_none() {
+            // Otherwise the stack is already in memory
+            for &entry in &self.stack[..self.stack_len] {
+                w.u16(entry);
+            }
+        }
+        w.u8(self.delay_timer);
+        w.u8(self.sound_timer);
+        write_timer(w, &self.ds_timer);
+        write_timer(w, &self.program_timer);
+
+        w.bool(self.hires);
+        w.u8(self.planes);
+        for pixels in self.framebuffer.chunks_exact(4) {
+            w.u8(pixels
+                .iter()
+                .enumerate()
+                .fold(0, |bits, (i, &pixel)| bits | (pixel & 0b11) << (i * 2)));
+        }
+        w.bytes(&self.color_map);
+        w.u8(self.background_color);
+
+        write_keys(w, &self.keys);
+        write_keys(w, &self.keys_2);
+        w.u8(self.key_events.len as u8);
+        for i in 0..self.key_events.len {
+            match self.key_events.events[(self.key_events.start + i) % KeyQueue::CAPACITY] {
+                KeyEvent::Press(key) => w.u8(key),
+                KeyEvent::Release(key) => w.u8(0x10 | key),
+            }
+        }
+        w.bool(self.pressed_key.is_some());
+        w.u8(self.pressed_key.unwrap_or(0));
+
+        w.bool(self.vblank);
+        w.bytes(&self.rpl_flags);
+        w.bool(self.exited);
+        w.bool(self.delay_wait);
+        w.u8(self.output_port);
+        w.u8(self.input_port);
+        w.bool(self.input_strobe);
+        w.bytes(&self.audio_pattern);
+        w.u8(self.pitch);
+
+        #[cfg(feature = "alloc")]
+        if self.config.variant == Variant::MegaChip {
+            self.write_megachip_state(w);
+        }
+    }
+
+    fn read_state(&mut self, r: &mut Reader<'_>) -> Result<(), Chip8Error> {
+        let memory_size = self.memory_size();
+        self.memory[..memory_size].copy_from_slice(r.bytes(memory_size)?);
+        // An instruction can only move pc a little past the end of memory, FX1B by up to 256 bytes
+        self.pc = r.usize_max(memory_size + 0x100)?;
+        self.index_reg = r.u16()?;
+        self.variable_reg = r.array()?;
+        self.stack_len = r.usize_max(Self::STACK_SIZE / 2)?;
+        if self.config.stack_address.is_none() {
+            for i in 0..self.stack_len {
+                self.stack[i] = r.u16()?;
+            }
+        }
+        self.delay_timer = r.u8()?;
+        self.sound_timer = r.u8()?;
+        read_timer(r, &mut self.ds_timer)?;
+        read_timer(r, &mut self.program_timer)?;
+
+        self.hires = r.bool()?;
+        self.planes = r.u8_max(0b11)?;
+        for pixels in self.framebuffer.chunks_exact_mut(4) {
+            let bits = r.u8()?;
+            for (i, pixel) in pixels.iter_mut().enumerate() {
+                *pixel = (bits >> (i * 2)) & 0b11;
+            }
+        }
+        self.color_map = r.array()?;
+        self.background_color = r.u8_max(3)?;
+
+        self.keys = read_keys(r)?;
+        self.keys_2 = read_keys(r)?;
+        let key_events = r.u8_max(KeyQueue::CAPACITY as u8)?;
+        for _ in 0..key_events {
+            let event = r.u8_max(0x1F)?;
+            self.key_events.push(if event & 0x10 == 0 {
+                KeyEvent::Press(event)
+            } else {
+                KeyEvent::Release(event & 0xF)
+            });
+        }
+        let has_pressed_key = r.bool()?;
+        let pressed_key = r.u8_max(0xF)?;
+        self.pressed_key = has_pressed_key.then_some(pressed_key);
+
+        self.vblank = r.bool()?;
+        self.rpl_flags = r.array()?;
+        self.exited = r.bool()?;
+        self.delay_wait = r.bool()?;
+        self.output_port = r.u8()?;
+        self.input_port = r.u8()?;
+        self.input_strobe = r.bool()?;
+        self.audio_pattern = r.array()?;
+        self.pitch = r.u8()?;
+
+        #[cfg(feature = "alloc")]
+        if self.config.variant == Variant::MegaChip {
+            self.read_megachip_state(r)?;
+        }
+        Ok(())
+    }
+}
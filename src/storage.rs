@@ -0,0 +1,36 @@
+//! `FlagStorage` backed by a file, needs the `std` feature
+
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use crate::{Chip8Error, FlagStorage};
+
+/// Saves the RPL user flags as 16 raw bytes in a file, usually one per game
+pub struct FileFlagStorage {
+    path: PathBuf,
+}
+
+impl FileFlagStorage {
+    /// The file is created by the first `FX75`, loading before that leaves the flags as they are
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl FlagStorage for FileFlagStorage {
+    fn save(&mut self, flags: &[u8; 16]) -> Result<(), Chip8Error> {
+        std::fs::write(&self.path, flags).map_err(|_| Chip8Error::FlagStorage)
+    }
+
+    fn load(&mut self, flags: &mut [u8; 16]) -> Result<(), Chip8Error> {
+        match std::fs::read(&self.path) {
+            Ok(saved) => {
+                let len = saved.len().min(flags.len());
+                flags[..len].copy_from_slice(&saved[..len]);
+                Ok(())
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(_) => Err(Chip8Error::FlagStorage),
+        }
+    }
+}
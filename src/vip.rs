@@ -0,0 +1,535 @@
+//! Emulation of the whole COSMAC VIP: a CDP1802 CPU, the CDP1861 video chip and the hex keypad.
+//! The machine runs the original CHIP-8 interpreter, so its timing and 0NNN routines behave exactly like the real thing.
+
+use crate::{Chip8, Chip8Config, Chip8Error};
+
+/// Registers of the RCA CDP1802
+struct Cdp1802 {
+    r: [u16; 16],
+    d: u8,
+    df: bool,
+    p: u8,
+    x: u8,
+    t: u8,
+    ie: bool,
+    q: bool,
+    /// Set by IDL until the next DMA or interrupt
+    idle: bool,
+}
+
+impl Cdp1802 {
+    /// State after a reset, the program counter is R0
+    const fn new() -> Self {
+        Self {
+            r: [0; 16],
+            d: 0,
+            df: false,
+            p: 0,
+            x: 0,
+            t: 0,
+            ie: true,
+            q: false,
+            idle: false,
+        }
+    }
+}
+
+pub struct CosmacVip {
+    /// Pixels are stored row by row, `width() * height()` pixels are used.
+    /// Every CDP1861 display line is a row, so each CHIP-8 row is usually repeated 4 times.
+    pub framebuffer: [u8; Chip8::HIRES_WIDTH * Chip8::HIRES_HEIGHT],
+    /// In microseconds, the most time one `CosmacVip::update` catches up on, like `Chip8Config::max_catch_up`
+    pub max_catch_up: u32,
+    cpu: Cdp1802,
+    ram: [u8; Self::RAM_SIZE],
+    rom: [u8; Self::ROM_SIZE],
+    /// The ROM shows up at 0x0000 after a reset, until the first access above 0x8000
+    rom_at_zero: bool,
+    keys: [bool; 16],
+    /// Key selected by `OUT 2`, EF3 tells whether it is pressed
+    key_latch: u8,
+    display_on: bool,
+    /// Machine cycle within the current frame
+    frame_cycle: usize,
+    /// Last display line the CDP1861 fetched with DMA
+    dma_line: Option<usize>,
+    /// Microseconds times machine cycles per second that didn't add up to a whole machine cycle yet
+    cycle_remainder: u64,
+    /// Machine cycles `update` still has to run, instructions can overshoot it
+    cycle_debt: i64,
+}
+
+impl CosmacVip {
+    /// In bytes, the VIP came with 2 KiB and could be expanded to 4 KiB on the board
+    pub const RAM_SIZE: usize = 4096;
+    /// In bytes, the monitor ROM is mapped at 0x8000
+    pub const ROM_SIZE: usize = 512;
+    /// In bytes, the CHIP-8 interpreter is loaded at 0x0000
+    pub const INTERPRETER_SIZE: usize = 512;
+    pub const PROGRAM_START: usize = 0x200;
+    /// The 1.76064 MHz clock divided by 8 clocks per machine cycle
+    pub const MACHINE_CYCLES_PER_SECOND: u64 = 220_080;
+    pub const WIDTH: usize = 64;
+    pub const HEIGHT: usize = 128;
+
+    const CYCLES_PER_LINE: usize = 14;
+    const LINES_PER_FRAME: usize = 262;
+    const DISPLAY_START: usize = 80;
+    /// Machine cycle within a line at which the CDP1861 starts its 8 DMA cycles
+    const DMA_START: usize = 2;
+
+    /// `monitor_rom` is the 512-byte VIP operating system and `interpreter` the 512-byte CHIP-8 interpreter.
+    /// Both images have to be supplied by the user. The machine starts like it does after the RUN switch is flipped,
+    /// the monitor then starts the interpreter as long as the C key isn't held down.
+    pub fn new(
+        monitor_rom: &[u8; Self::ROM_SIZE],
+        interpreter: &[u8; Self::INTERPRETER_SIZE],
+    ) -> Self {
+        let mut ram = [0; Self::RAM_SIZE];
+        ram[..Self::INTERPRETER_SIZE].copy_from_slice(interpreter);
+        Self {
+            framebuffer: [0; Chip8::HIRES_WIDTH * Chip8::HIRES_HEIGHT],
+            max_catch_up: Chip8Config::MAX_CATCH_UP,
+            cpu: Cdp1802::new(),
+            ram,
+            rom: *monitor_rom,
+            rom_at_zero: true,
+            keys: [false; 16],
+            key_latch: 0,
+            display_on: false,
+            frame_cycle: 0,
+            dma_line: None,
+            cycle_remainder: 0,
+            cycle_debt: 0,
+        }
+    }
+
+    /// Loads a CHIP-8 program at 0x200
+    pub fn set_program(&mut self, program: &[u8]) -> Result<(), Chip8Error> {
+        let max_size = Self::RAM_SIZE - Self::PROGRAM_START;
+        if program.len() > max_size {
+            return Err(Chip8Error::ProgramTooLarge {
+                size: program.len(),
+                max_size,
+            });
+        }
+        self.ram[Self::PROGRAM_START..Self::PROGRAM_START + program.len()].copy_from_slice(program);
+        Ok(())
+    }
+
+    /// The RAM, with the interpreter at 0x0000 and the program at `CosmacVip::PROGRAM_START`
+    pub fn memory(&self) -> &[u8] {
+        &self.ram
+    }
+
+    /// The tone generator is driven by the Q output of the CPU
+    pub fn should_play_sound(&self) -> bool {
+        self.cpu.q
+    }
+
+    pub fn width(&self) -> usize {
+        Self::WIDTH
+    }
+
+    pub fn height(&self) -> usize {
+        Self::HEIGHT
+    }
+
+    pub fn press(&mut self, key: u8) {
+        self.keys[key as usize] = true;
+    }
+
+    pub fn release(&mut self, key: u8) {
+        self.keys[key as usize] = false;
+    }
+
+    /// Runs every machine cycle due in `delta` microseconds, up to `CosmacVip::max_catch_up`
+    pub fn update(&mut self, delta: u128) {
+        let delta = u32::try_from(delta)
+            .unwrap_or(u32::MAX)
+            .min(self.max_catch_up);
+        self.cycle_remainder += delta as u64 * Self::MACHINE_CYCLES_PER_SECOND;
+        self.cycle_debt += (self.cycle_remainder / 1_000_000) as i64;
+        self.cycle_remainder %= 1_000_000;
+        while self.cycle_debt > 0 {
+            let cycles = self.step();
+            self.advance(cycles);
+        }
+    }
+
+    fn read(&mut self, address: u16) -> u8 {
+        if address & 0x8000 != 0 {
+            self.rom_at_zero = false;
+            self.rom[address as usize % Self::ROM_SIZE]
+        } else if self.rom_at_zero {
+            self.rom[address as usize % Self::ROM_SIZE]
+        } else {
+            self.ram[address as usize % Self::RAM_SIZE]
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if address & 0x8000 == 0 {
+            self.ram[address as usize % Self::RAM_SIZE] = value;
+        }
+    }
+
+    fn line(&self) -> usize {
+        self.frame_cycle / Self::CYCLES_PER_LINE
+    }
+
+    /// CDP1861 interrupt request, raised two lines before the display starts
+    fn interrupt_request(&self) -> bool {
+        self.display_on && (Self::DISPLAY_START - 2..Self::DISPLAY_START).contains(&self.line())
+    }
+
+    /// CDP1861 status on EF1, raised four lines before the start and the end of the display
+    fn ef1(&self) -> bool {
+        let line = self.line();
+        let end = Self::DISPLAY_START + Self::HEIGHT;
+        self.display_on
+            && ((Self::DISPLAY_START - 4..Self::DISPLAY_START).contains(&line)
+                || (end - 4..end).contains(&line))
+    }
+
+    fn advance(&mut self, cycles: usize) {
+        self.cycle_debt -= cycles as i64;
+        self.frame_cycle += cycles;
+        if self.frame_cycle >= Self::CYCLES_PER_LINE * Self::LINES_PER_FRAME {
+            self.frame_cycle -= Self::CYCLES_PER_LINE * Self::LINES_PER_FRAME;
+            self.dma_line = None;
+        }
+    }
+
+    /// Runs a DMA transfer, an interrupt or an instruction and returns how many machine cycles it took
+    fn step(&mut self) -> usize {
+        let line = self.line();
+        if self.display_on
+            && (Self::DISPLAY_START..Self::DISPLAY_START + Self::HEIGHT).contains(&line)
+            && self.frame_cycle % Self::CYCLES_PER_LINE >= Self::DMA_START
+            && self.dma_line != Some(line)
+        {
+            // The CDP1861 fetches a line of 8 bytes from R0
+            self.dma_line = Some(line);
+            let row = line - Self::DISPLAY_START;
+            for i in 0..8 {
+                let byte = self.read(self.cpu.r[0]);
+                self.cpu.r[0] = self.cpu.r[0].wrapping_add(1);
+                for bit in 0..8 {
+                    self.framebuffer[row * Self::WIDTH + i * 8 + bit] = (byte >> (7 - bit)) & 1;
+                }
+            }
+            self.cpu.idle = false;
+            return 8;
+        }
+        if self.cpu.ie && self.interrupt_request() {
+            let cpu = &mut self.cpu;
+            cpu.t = (cpu.x << 4) | cpu.p;
+            cpu.p = 1;
+            cpu.x = 2;
+            cpu.ie = false;
+            cpu.idle = false;
+            return 1;
+        }
+        if self.cpu.idle {
+            return 1;
+        }
+        self.execute()
+    }
+
+    fn fetch(&mut self) -> u8 {
+        let p = self.cpu.p as usize;
+        let value = self.read(self.cpu.r[p]);
+        self.cpu.r[p] = self.cpu.r[p].wrapping_add(1);
+        value
+    }
+
+    /// Executes the instruction at R(P) and returns how many machine cycles it took
+    fn execute(&mut self) -> usize {
+        let opcode = self.fetch();
+        let (i, n) = (opcode >> 4, (opcode & 0xF) as usize);
+        let x = self.cpu.x as usize;
+        let p = self.cpu.p as usize;
+        match i {
+            0x0 if n == 0 => {
+                // IDL : wait for DMA or an interrupt
+                self.cpu.idle = true;
+            }
+            0x0 => {
+                // LDN : D = M(R(N))
+                self.cpu.d = self.read(self.cpu.r[n]);
+            }
+            0x1 => {
+                // INC
+                self.cpu.r[n] = self.cpu.r[n].wrapping_add(1);
+            }
+            0x2 => {
+                // DEC
+                self.cpu.r[n] = self.cpu.r[n].wrapping_sub(1);
+            }
+            0x3 => {
+                // Short branches, 0x38 is SKP
+                let taken = match n & 0x7 {
+                    0x0 => true,
+                    0x1 => self.cpu.q,
+                    0x2 => self.cpu.d == 0,
+                    0x3 => self.cpu.df,
+                    ef => self.ef(ef as u8 - 3),
+                };
+                let taken = if n >= 0x8 { !taken } else { taken };
+                if taken {
+                    let low = self.read(self.cpu.r[p]);
+                    self.cpu.r[p] = (self.cpu.r[p] & 0xFF00) | low as u16;
+                } else {
+                    self.cpu.r[p] = self.cpu.r[p].wrapping_add(1);
+                }
+            }
+            0x4 => {
+                // LDA : D = M(R(N)), R(N) += 1
+                self.cpu.d = self.read(self.cpu.r[n]);
+                self.cpu.r[n] = self.cpu.r[n].wrapping_add(1);
+            }
+            0x5 => {
+                // STR : M(R(N)) = D
+                self.write(self.cpu.r[n], self.cpu.d);
+            }
+            0x6 if n == 0 => {
+                // IRX
+                self.cpu.r[x] = self.cpu.r[x].wrapping_add(1);
+            }
+            0x6 if n < 8 => {
+                // OUT N : bus = M(R(X)), R(X) += 1
+                let value = self.read(self.cpu.r[x]);
+                self.cpu.r[x] = self.cpu.r[x].wrapping_add(1);
+                self.output(n, value);
+            }
+            0x6 if n == 8 => (),
+            0x6 => {
+                // INP N : M(R(X)) = D = bus
+                let value = self.input(n - 8);
+                self.write(self.cpu.r[x], value);
+                self.cpu.d = value;
+            }
+            0x7 => self.execute_7(n),
+            0x8 => {
+                // GLO
+                self.cpu.d = self.cpu.r[n] as u8;
+            }
+            0x9 => {
+                // GHI
+                self.cpu.d = (self.cpu.r[n] >> 8) as u8;
+            }
+            0xA => {
+                // PLO
+                self.cpu.r[n] = (self.cpu.r[n] & 0xFF00) | self.cpu.d as u16;
+            }
+            0xB => {
+                // PHI
+                self.cpu.r[n] = (self.cpu.r[n] & 0x00FF) | ((self.cpu.d as u16) << 8);
+            }
+            0xC => {
+                self.execute_long(n);
+                return 3;
+            }
+            0xD => {
+                // SEP
+                self.cpu.p = n as u8;
+            }
+            0xE => {
+                // SEX
+                self.cpu.x = n as u8;
+            }
+            _ => self.execute_f(n),
+        }
+        2
+    }
+
+    fn execute_7(&mut self, n: usize) {
+        let x = self.cpu.x as usize;
+        match n {
+            0x0 | 0x1 => {
+                // RET / DIS : X, P = M(R(X)), R(X) += 1
+                let value = self.read(self.cpu.r[x]);
+                self.cpu.r[x] = self.cpu.r[x].wrapping_add(1);
+                self.cpu.x = value >> 4;
+                self.cpu.p = value & 0xF;
+                self.cpu.ie = n == 0x0;
+            }
+            0x2 => {
+                // LDXA : D = M(R(X)), R(X) += 1
+                self.cpu.d = self.read(self.cpu.r[x]);
+                self.cpu.r[x] = self.cpu.r[x].wrapping_add(1);
+            }
+            0x3 => {
+                // STXD : M(R(X)) = D, R(X) -= 1
+                self.write(self.cpu.r[x], self.cpu.d);
+                self.cpu.r[x] = self.cpu.r[x].wrapping_sub(1);
+            }
+            0x6 => {
+                // SHRC : shift right through DF
+                let carry = self.cpu.d & 1 != 0;
+                self.cpu.d = (self.cpu.d >> 1) | ((self.cpu.df as u8) << 7);
+                self.cpu.df = carry;
+            }
+            0x8 => {
+                // SAV : M(R(X)) = T
+                self.write(self.cpu.r[x], self.cpu.t);
+            }
+            0x9 => {
+                // MARK : T = X, P; M(R2) = T; X = P; R2 -= 1
+                self.cpu.t = (self.cpu.x << 4) | self.cpu.p;
+                self.write(self.cpu.r[2], self.cpu.t);
+                self.cpu.x = self.cpu.p;
+                self.cpu.r[2] = self.cpu.r[2].wrapping_sub(1);
+            }
+            0xA => {
+                // REQ
+                self.cpu.q = false;
+            }
+            0xB => {
+                // SEQ
+                self.cpu.q = true;
+            }
+            0xE => {
+                // SHLC : shift left through DF
+                let carry = self.cpu.d & 0x80 != 0;
+                self.cpu.d = (self.cpu.d << 1) | self.cpu.df as u8;
+                self.cpu.df = carry;
+            }
+            _ => {
+                // ADC, SDB, SMB and their immediate forms ADCI, SDBI, SMBI
+                let operand = if n >= 0xC {
+                    self.fetch()
+                } else {
+                    self.read(self.cpu.r[x])
+                };
+                let carry = self.cpu.df as u8;
+                match n & 0x3 {
+                    0x0 => self.add(operand, carry),
+                    0x1 => self.subtract(operand, self.cpu.d, carry),
+                    _ => self.subtract(self.cpu.d, operand, carry),
+                }
+            }
+        }
+    }
+
+    /// Long branches and long skips
+    fn execute_long(&mut self, n: usize) {
+        let p = self.cpu.p as usize;
+        let condition = match n & 0x3 {
+            0x0 => true,
+            0x1 => self.cpu.q,
+            0x2 => self.cpu.d == 0,
+            _ => self.cpu.df,
+        };
+        match n {
+            0x4 => (), // NOP
+            0xC => {
+                // LSIE
+                if self.cpu.ie {
+                    self.cpu.r[p] = self.cpu.r[p].wrapping_add(2);
+                }
+            }
+            0x0..=0x3 | 0x8..=0xB if n != 0x8 => {
+                // LBR, LBQ, LBZ, LBDF, LBNQ, LBNZ, LBNF
+                let taken = if n >= 0x8 { !condition } else { condition };
+                if taken {
+                    let high = self.read(self.cpu.r[p]);
+                    let low = self.read(self.cpu.r[p].wrapping_add(1));
+                    self.cpu.r[p] = u16::from_be_bytes([high, low]);
+                } else {
+                    self.cpu.r[p] = self.cpu.r[p].wrapping_add(2);
+                }
+            }
+            _ => {
+                // LSNQ, LSNZ, LSNF, LSKP, LSQ, LSZ, LSDF
+                let taken = match n {
+                    0x8 => true,
+                    0x5..=0x7 => !condition,
+                    _ => condition,
+                };
+                if taken {
+                    self.cpu.r[p] = self.cpu.r[p].wrapping_add(2);
+                }
+            }
+        }
+    }
+
+    fn execute_f(&mut self, n: usize) {
+        let x = self.cpu.x as usize;
+        if n == 0x0 {
+            // LDX
+            self.cpu.d = self.read(self.cpu.r[x]);
+            return;
+        }
+        if n & 0x7 == 0x6 {
+            // SHR and SHL
+            if n == 0x6 {
+                self.cpu.df = self.cpu.d & 1 != 0;
+                self.cpu.d >>= 1;
+            } else {
+                self.cpu.df = self.cpu.d & 0x80 != 0;
+                self.cpu.d <<= 1;
+            }
+            return;
+        }
+        // OR, AND, XOR, ADD, SD, SM and their immediate forms from LDI on
+        let operand = if n >= 0x8 {
+            self.fetch()
+        } else {
+            self.read(self.cpu.r[x])
+        };
+        match n & 0x7 {
+            0x0 => self.cpu.d = operand, // LDI
+            0x1 => self.cpu.d |= operand,
+            0x2 => self.cpu.d &= operand,
+            0x3 => self.cpu.d ^= operand,
+            0x4 => self.add(operand, 0),
+            0x5 => self.subtract(operand, self.cpu.d, 1),
+            _ => self.subtract(self.cpu.d, operand, 1),
+        }
+    }
+
+    /// D = D + `operand` + `carry`, DF is the carry out
+    fn add(&mut self, operand: u8, carry: u8) {
+        let sum = self.cpu.d as u16 + operand as u16 + carry as u16;
+        self.cpu.d = sum as u8;
+        self.cpu.df = sum > 0xFF;
+    }
+
+    /// D = `a` - `b` - !`carry`, DF is set if there was no borrow
+    fn subtract(&mut self, a: u8, b: u8, carry: u8) {
+        let difference = a as i16 - b as i16 - (1 - carry as i16);
+        self.cpu.d = difference as u8;
+        self.cpu.df = difference >= 0;
+    }
+
+    /// External flag inputs: EF1 is the CDP1861 and EF3 the keypad
+    fn ef(&self, flag: u8) -> bool {
+        match flag {
+            1 => self.ef1(),
+            3 => self.keys[self.key_latch as usize],
+            _ => false,
+        }
+    }
+
+    fn output(&mut self, port: usize, value: u8) {
+        match port {
+            1 => {
+                // Turns the CDP1861 off
+                self.display_on = false;
+                self.framebuffer.fill(0);
+            }
+            2 => self.key_latch = value & 0xF,
+            _ => (),
+        }
+    }
+
+    fn input(&mut self, port: usize) -> u8 {
+        if port == 1 {
+            // Turns the CDP1861 on
+            self.display_on = true;
+        }
+        0
+    }
+}
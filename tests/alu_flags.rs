@@ -0,0 +1,109 @@
+use chip8::{Chip8, Chip8Config};
+
+/// Loads `va`, `vb` and `vf`, then runs `instruction` and returns every register
+fn run(config: Chip8Config, va: u8, vb: u8, vf: u8, instruction: [u8; 2]) -> [u8; 16] {
+    #[rustfmt::skip]
+    let program = [
+        0x6A, va, // vA := va
+        0x6B, vb, // vB := vb
+        0x6F, vf, // vF := vf
+        instruction[0], instruction[1],
+    ];
+    let mut chip8 = Chip8::new(config);
+    chip8.set_program(&program).unwrap();
+    for _ in 0..4 {
+        chip8.step(|| 0).unwrap();
+    }
+    *chip8.variable_reg()
+}
+
+#[test]
+fn add_sets_carry() {
+    // vA += vB
+    let reg = run(Chip8Config::modern(), 0xFF, 0x02, 0x55, [0x8A, 0xB4]);
+    assert_eq!((reg[0xA], reg[0xF]), (0x01, 1));
+    let reg = run(Chip8Config::modern(), 0x10, 0x20, 0x55, [0x8A, 0xB4]);
+    assert_eq!((reg[0xA], reg[0xF]), (0x30, 0));
+}
+
+#[test]
+fn add_into_vf_keeps_the_flag() {
+    // vF += vA, the sum 0x00 is overwritten by the carry
+    let reg = run(Chip8Config::modern(), 0x01, 0x00, 0xFF, [0x8F, 0xA4]);
+    assert_eq!(reg[0xF], 1);
+    // vA += vF, the carry is computed from the old vF
+    let reg = run(Chip8Config::modern(), 0x80, 0x00, 0x80, [0x8A, 0xF4]);
+    assert_eq!((reg[0xA], reg[0xF]), (0x00, 1));
+}
+
+#[test]
+fn subtract_sets_no_borrow() {
+    // vA -= vB
+    let reg = run(Chip8Config::modern(), 0x05, 0x07, 0x55, [0x8A, 0xB5]);
+    assert_eq!((reg[0xA], reg[0xF]), (0xFE, 0));
+    let reg = run(Chip8Config::modern(), 0x07, 0x07, 0x55, [0x8A, 0xB5]);
+    assert_eq!((reg[0xA], reg[0xF]), (0x00, 1));
+}
+
+#[test]
+fn subtract_into_vf_keeps_the_flag() {
+    // vF -= vA, the difference 0x02 is overwritten by the flag
+    let reg = run(Chip8Config::modern(), 0x05, 0x00, 0x07, [0x8F, 0xA5]);
+    assert_eq!(reg[0xF], 1);
+    // vA -= vF
+    let reg = run(Chip8Config::modern(), 0x05, 0x00, 0x07, [0x8A, 0xF5]);
+    assert_eq!((reg[0xA], reg[0xF]), (0xFE, 0));
+}
+
+#[test]
+fn subtract_reverse_sets_no_borrow() {
+    // vA := vB - vA
+    let reg = run(Chip8Config::modern(), 0x07, 0x05, 0x55, [0x8A, 0xB7]);
+    assert_eq!((reg[0xA], reg[0xF]), (0xFE, 0));
+    let reg = run(Chip8Config::modern(), 0x05, 0x07, 0x55, [0x8A, 0xB7]);
+    assert_eq!((reg[0xA], reg[0xF]), (0x02, 1));
+}
+
+#[test]
+fn subtract_reverse_into_vf_keeps_the_flag() {
+    // vF := vA - vF, the difference 0x06 is overwritten by the flag
+    let reg = run(Chip8Config::modern(), 0x09, 0x00, 0x03, [0x8F, 0xA7]);
+    assert_eq!(reg[0xF], 1);
+    // vA := vF - vA
+    let reg = run(Chip8Config::modern(), 0x09, 0x00, 0x03, [0x8A, 0xF7]);
+    assert_eq!((reg[0xA], reg[0xF]), (0xFA, 0));
+}
+
+#[test]
+fn shift_left_sets_the_shifted_out_bit() {
+    // vA <<= vA
+    let reg = run(Chip8Config::modern(), 0x81, 0x00, 0x55, [0x8A, 0xAE]);
+    assert_eq!((reg[0xA], reg[0xF]), (0x02, 1));
+    let reg = run(Chip8Config::modern(), 0x41, 0x00, 0x55, [0x8A, 0xAE]);
+    assert_eq!((reg[0xA], reg[0xF]), (0x82, 0));
+}
+
+#[test]
+fn shift_left_into_vf_keeps_the_flag() {
+    // vF <<= vF, the result 0x00 is overwritten by the flag
+    let reg = run(Chip8Config::modern(), 0x00, 0x00, 0x80, [0x8F, 0xFE]);
+    assert_eq!(reg[0xF], 1);
+    // vA <<= vF with the COSMAC VIP quirk shifts vF into vA
+    let reg = run(Chip8Config::cosmac_vip(), 0x00, 0x00, 0x40, [0x8A, 0xFE]);
+    assert_eq!((reg[0xA], reg[0xF]), (0x80, 0));
+}
+
+#[test]
+fn logic_ops_reset_vf() {
+    // vA |= vB, vA &= vB and vA ^= vB
+    for (instruction, result) in [
+        ([0x8A, 0xB1], 0x3F),
+        ([0x8A, 0xB2], 0x0C),
+        ([0x8A, 0xB3], 0x33),
+    ] {
+        let reg = run(Chip8Config::cosmac_vip(), 0x0F, 0x3C, 0x55, instruction);
+        assert_eq!((reg[0xA], reg[0xF]), (result, 0));
+        let reg = run(Chip8Config::modern(), 0x0F, 0x3C, 0x55, instruction);
+        assert_eq!((reg[0xA], reg[0xF]), (result, 0x55));
+    }
+}
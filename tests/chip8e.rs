@@ -0,0 +1,118 @@
+use chip8::{Chip8, Chip8Config};
+
+fn chip8(program: &[u8]) -> Chip8 {
+    let mut chip8 = Chip8::new(Chip8Config::chip8e());
+    chip8.set_program(program).unwrap();
+    chip8
+}
+
+fn steps(chip8: &mut Chip8, steps: usize) {
+    for _ in 0..steps {
+        chip8.step(|| 0).unwrap();
+    }
+}
+
+#[test]
+fn stop() {
+    let mut chip8 = chip8(&[0x00, 0xED, 0x60, 0x01]);
+    steps(&mut chip8, 3);
+    assert_eq!(chip8.pc(), 0x202);
+    assert_eq!(chip8.variable_reg()[0], 0);
+}
+
+#[test]
+fn skips() {
+    #[rustfmt::skip]
+    let mut chip8 = chip8(&[
+        0x01, 0x88, // skip
+        0x60, 0x01, // v0 := 1
+        0x61, 0x05, // v1 := 5
+        0x51, 0x01, // if v1 <= v0 then
+        0x62, 0x01, // v2 := 1
+        0xF1, 0x1B, // skip v1 bytes
+        0x63, 0x01, // v3 := 1
+        0x00, 0x00,
+        0x00,
+        0x64, 0x01, // v4 := 1
+    ]);
+    steps(&mut chip8, 5);
+    assert_eq!(chip8.variable_reg()[..5], [0, 5, 0, 0, 1]);
+    assert_eq!(chip8.pc(), 0x213);
+}
+
+#[test]
+fn relative_jumps() {
+    #[rustfmt::skip]
+    let mut chip8 = chip8(&[
+        0xBF, 0x04, // jump forward 4
+        0x00, 0x00,
+        0x00, 0x00,
+        0xBB, 0x08, // jump back 8
+    ]);
+    steps(&mut chip8, 1);
+    assert_eq!(chip8.pc(), 0x206);
+    steps(&mut chip8, 1);
+    assert_eq!(chip8.pc(), 0x200);
+}
+
+#[test]
+fn wait_delay() {
+    #[rustfmt::skip]
+    let mut chip8 = chip8(&[
+        0x60, 0x02, // v0 := 2
+        0xF0, 0x15, // delay := v0
+        0x01, 0x51, // wait until delay = 0
+    ]);
+    steps(&mut chip8, 3);
+    assert_eq!(chip8.pc(), 0x204);
+    chip8.tick_timers();
+    chip8.tick_timers();
+    steps(&mut chip8, 1);
+    assert_eq!(chip8.pc(), 0x206);
+}
+
+#[test]
+fn delay_and_wait() {
+    #[rustfmt::skip]
+    let mut chip8 = chip8(&[
+        0x60, 0x03, // v0 := 3
+        0xF0, 0x4F, // delay v0
+        0xF0, 0x4F, // delay v0
+    ]);
+    steps(&mut chip8, 2);
+    assert_eq!((chip8.pc(), chip8.delay_timer()), (0x202, 3));
+    // The delay is only set when the wait starts
+    chip8.tick_timers();
+    steps(&mut chip8, 1);
+    assert_eq!((chip8.pc(), chip8.delay_timer()), (0x202, 2));
+    chip8.tick_timers();
+    chip8.tick_timers();
+    steps(&mut chip8, 1);
+    assert_eq!(chip8.pc(), 0x204);
+    // The next one starts a new wait
+    steps(&mut chip8, 1);
+    assert_eq!((chip8.pc(), chip8.delay_timer()), (0x204, 3));
+}
+
+#[test]
+fn ports() {
+    #[rustfmt::skip]
+    let mut chip8 = chip8(&[
+        0x60, 0x42, // v0 := 0x42
+        0xF0, 0x03, // output v0
+        0xF1, 0xE3, // wait for input v1
+        0xF2, 0xE3, // wait for input v2
+        0xF3, 0xE7, // input v3
+    ]);
+    steps(&mut chip8, 3);
+    assert_eq!(chip8.output_port(), 0x42);
+    assert_eq!(chip8.pc(), 0x204);
+    chip8.set_input_port(0x17);
+    steps(&mut chip8, 2);
+    assert_eq!(chip8.variable_reg()[1], 0x17);
+    // The strobe was used up by the first wait
+    assert_eq!(chip8.pc(), 0x206);
+    chip8.set_input_port(0x18);
+    steps(&mut chip8, 2);
+    assert_eq!(chip8.variable_reg()[1..4], [0x17, 0x18, 0x18]);
+}
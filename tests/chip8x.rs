@@ -0,0 +1,92 @@
+use chip8::{Chip8, Chip8Config};
+
+/// Runs `steps` instructions of `program`, which is loaded at 0x300
+fn run(program: &[u8], steps: usize, setup: impl FnOnce(&mut Chip8)) -> Chip8 {
+    let mut chip8 = Chip8::new(Chip8Config::chip8x());
+    chip8.set_program(program).unwrap();
+    setup(&mut chip8);
+    for _ in 0..steps {
+        chip8.step(|| 0).unwrap();
+    }
+    chip8
+}
+
+/// Colours of the 8 zones of `row`
+fn zones(chip8: &Chip8, row: usize) -> &[u8] {
+    &chip8.color_map[row * 8..row * 8 + 8]
+}
+
+#[test]
+fn step_background() {
+    let chip8 = run(&[0x02, 0xA0, 0x13, 0x00], 1, |_| ());
+    assert_eq!(chip8.background_color(), 1);
+    // Blue, black, green, red and back to blue
+    let chip8 = run(&[0x02, 0xA0, 0x13, 0x00], 8, |_| ());
+    assert_eq!(chip8.background_color(), 0);
+}
+
+#[test]
+fn add_nibbles() {
+    #[rustfmt::skip]
+    let program = [
+        0x60, 0x35, // v0 := 0x35
+        0x61, 0x46, // v1 := 0x46
+        0x50, 0x11, // v0 +nibbles v1
+    ];
+    let chip8 = run(&program, 3, |_| ());
+    // 3 + 4 and 5 + 6, each modulo 8
+    assert_eq!(chip8.variable_reg()[0], 0x73);
+}
+
+#[test]
+fn color_zones() {
+    #[rustfmt::skip]
+    let program = [
+        0x60, 0x11, // v0 := 0x11 // From zone 1, one more to the right
+        0x61, 0x02, // v1 := 0x02 // Zone row 2 only
+        0x62, 0x05, // v2 := 5
+        0xB0, 0x20, // color-zones v0 v2
+    ];
+    let chip8 = run(&program, 4, |_| ());
+    for row in 8..12 {
+        assert_eq!(zones(&chip8, row), [1, 5, 5, 1, 1, 1, 1, 1]);
+    }
+    assert_eq!(zones(&chip8, 7), [1; 8]);
+    assert_eq!(zones(&chip8, 12), [1; 8]);
+}
+
+#[test]
+fn color_rows() {
+    #[rustfmt::skip]
+    let program = [
+        0x64, 0x10, // v4 := 16
+        0x65, 0x03, // v5 := 3
+        0x66, 0x1E, // v6 := 30
+        0xB4, 0x63, // color-rows v4 v6 3
+    ];
+    let chip8 = run(&program, 4, |_| ());
+    // Rows 30, 31 and then 0 at the top
+    for row in [30, 31, 0] {
+        assert_eq!(zones(&chip8, row), [1, 1, 3, 1, 1, 1, 1, 1]);
+    }
+    assert_eq!(zones(&chip8, 1), [1; 8]);
+}
+
+#[test]
+fn second_keypad() {
+    #[rustfmt::skip]
+    let program = [
+        0x60, 0x01, // v0 := 1
+        0xE0, 0xF2, // if v0 -key2 then
+        0x13, 0x00, // jump 0x300
+        0xE0, 0xF5, // if v0 key2 then
+        0x13, 0x00, // jump 0x300
+    ];
+    let chip8 = run(&program, 2, |chip8| chip8.press_second_keypad(1));
+    assert_eq!(chip8.pc(), 0x306);
+    let chip8 = run(&program, 3, |chip8| chip8.press_second_keypad(1));
+    assert_eq!(chip8.pc(), 0x308);
+    // The first keypad doesn't count
+    let chip8 = run(&program, 2, |chip8| chip8.press(1));
+    assert_eq!(chip8.pc(), 0x304);
+}
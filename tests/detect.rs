@@ -0,0 +1,133 @@
+use chip8::{Candidate, Chip8Config};
+
+fn best(rom: &[u8]) -> Chip8Config {
+    Chip8Config::detect(rom)[0].config.clone()
+}
+
+#[test]
+fn every_platform_is_ranked() {
+    let candidates = Chip8Config::detect(&[0x12, 0x00]);
+    assert_eq!(candidates.len(), Candidate::COUNT);
+    for config in [
+        Chip8Config::dream_6800(),
+        Chip8Config::eti_660(),
+        Chip8Config::chip8x(),
+        Chip8Config::chip8e(),
+    ] {
+        assert!(candidates
+            .iter()
+            .any(|candidate| candidate.config == config));
+    }
+}
+
+#[test]
+fn superchip() {
+    #[rustfmt::skip]
+    let rom = [
+        0x00, 0xFF, // hires
+        0x00, 0xC4, // scroll-down 4
+        0x00, 0xFB, // scroll-right
+        0x00, 0xFD, // exit
+    ];
+    assert_eq!(best(&rom), Chip8Config::schip_1_1());
+}
+
+#[test]
+fn odd_aligned_code() {
+    #[rustfmt::skip]
+    let rom = [
+        0x12, 0x03, // jump code
+        0xAA,       // one byte of data
+        // code:
+        0x00, 0xFF, // hires
+        0x00, 0xC4, // scroll-down 4
+        0x00, 0xFB, // scroll-right
+        0x00, 0xFD, // exit
+    ];
+    assert_eq!(best(&rom), Chip8Config::schip_1_1());
+}
+
+#[test]
+fn xo_chip() {
+    #[rustfmt::skip]
+    let rom = [
+        0xF0, 0x00, 0x02, 0x0A, // i := long data
+        0xF2, 0x01, // plane 2
+        0x50, 0x12, // save v0 - v1
+        0x12, 0x08, // loop
+        // data:
+        0x00, 0x00,
+    ];
+    assert_eq!(best(&rom), Chip8Config::xo_chip());
+}
+
+#[test]
+fn chip8x() {
+    // Loaded at 0x300
+    #[rustfmt::skip]
+    let rom = [
+        0x02, 0xA0, // step the background colour
+        0x60, 0x01, // v0 := 1
+        0xE0, 0xF2, // if v0 -key2 then
+        0x13, 0x06, // loop
+        0x13, 0x08, // loop
+    ];
+    assert_eq!(best(&rom), Chip8Config::chip8x());
+}
+
+#[test]
+fn chip8e() {
+    #[rustfmt::skip]
+    let rom = [
+        0x60, 0x05, // v0 := 5
+        0xF0, 0x4F, // delay v0
+        0x00, 0xED, // stop
+    ];
+    assert_eq!(best(&rom), Chip8Config::chip8e());
+}
+
+#[test]
+fn eti_660() {
+    // Loaded at 0x600, the jumps and calls land outside of the ROM anywhere else
+    #[rustfmt::skip]
+    let rom = [
+        0x60, 0x00, // v0 := 0
+        0x26, 0x08, // count
+        0x16, 0x04, // loop
+        0x00, 0x00,
+        // count:
+        0x70, 0x01, // v0 += 1
+        0x00, 0xEE, // return
+    ];
+    assert_eq!(best(&rom), Chip8Config::eti_660());
+}
+
+#[test]
+fn dream_6800_ranks_below_cosmac_vip() {
+    #[rustfmt::skip]
+    let rom = [
+        0x01, 0x23, // machine code routine
+        0x12, 0x02, // loop
+    ];
+    let candidates = Chip8Config::detect(&rom);
+    assert_eq!(candidates[0].config, Chip8Config::cosmac_vip());
+    assert_eq!(candidates[1].config, Chip8Config::dream_6800());
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn megachip() {
+    #[rustfmt::skip]
+    let rom = [
+        0x00, 0x11, // megaon
+        0x12, 0x02, // loop
+    ];
+    assert_eq!(best(&rom), Chip8Config::megachip());
+}
+
+#[test]
+fn too_large_for_any_platform() {
+    // Straight-line code longer than any memory
+    let candidates = Chip8Config::detect(&[0x60; 70_000]);
+    assert!(candidates.iter().all(|candidate| candidate.confidence == 0));
+}
@@ -0,0 +1,50 @@
+use chip8::{Chip8, Chip8Config};
+
+#[rustfmt::skip]
+const PROGRAM: [u8; 8] = [
+    0xD0, 0x01, // sprite v0 v0 1
+    0x71, 0x01, // v1 += 1
+    0x12, 0x00, // loop
+    0x00, 0x00,
+];
+
+fn chip8(display_wait: bool) -> Chip8 {
+    let mut chip8 = Chip8::new(Chip8Config {
+        display_wait,
+        ..Chip8Config::modern()
+    });
+    chip8.set_program(&PROGRAM).unwrap();
+    chip8
+}
+
+fn draws(chip8: &Chip8) -> u8 {
+    chip8.variable_reg()[1]
+}
+
+#[test]
+fn one_draw_per_frame() {
+    let mut chip8 = chip8(true);
+    // Waits for the first frame to start
+    for _ in 0..9 {
+        chip8.step(|| 0).unwrap();
+    }
+    assert_eq!((chip8.pc(), draws(&chip8)), (0x200, 0));
+    chip8.tick_timers();
+    for _ in 0..9 {
+        chip8.step(|| 0).unwrap();
+    }
+    assert_eq!((chip8.pc(), draws(&chip8)), (0x200, 1));
+
+    // Nine more frames start in just under 1/6 s, there's time for a lot more draws than that
+    chip8.update(1_000_000 / 6, || 0).unwrap();
+    assert_eq!(draws(&chip8), 10);
+}
+
+#[test]
+fn without_display_wait() {
+    let mut chip8 = chip8(false);
+    for _ in 0..9 {
+        chip8.step(|| 0).unwrap();
+    }
+    assert_eq!(draws(&chip8), 3);
+}
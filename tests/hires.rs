@@ -0,0 +1,52 @@
+use chip8::{Chip8, Chip8Config};
+
+fn program() -> Vec<u8> {
+    let mut program = vec![0x12, 0x60]; // jump 0x260 // Starts the patched interpreter
+    program.resize(0xC0, 0);
+    #[rustfmt::skip]
+    program.extend([
+        0x60, 0x28, // v0 := 40
+        0xA2, 0xCA, // i := sprite
+        0xD0, 0x01, // sprite v0 v0 1
+        0x02, 0x30, // hires-clear
+        0x12, 0xC8, // loop
+        0x80,       // sprite: one pixel
+    ]);
+    program
+}
+
+fn run(config: Chip8Config, steps: usize) -> Chip8 {
+    let mut chip8 = Chip8::new(config);
+    chip8.set_program(&program()).unwrap();
+    for _ in 0..steps {
+        // Every instruction gets a new frame, so sprites are drawn right away
+        chip8.tick_timers();
+        chip8.step(|| 0).unwrap();
+    }
+    chip8
+}
+
+#[test]
+fn starts_at_0x2c0() {
+    let chip8 = run(Chip8Config::chip8_hires(), 1);
+    assert_eq!(chip8.pc(), 0x2C0);
+    // Without the HIRES interpreter it is a plain jump
+    let chip8 = run(Chip8Config::cosmac_vip(), 1);
+    assert_eq!(chip8.pc(), 0x260);
+}
+
+#[test]
+fn draws_on_64_rows() {
+    let chip8 = run(Chip8Config::chip8_hires(), 4);
+    assert_eq!((chip8.width(), chip8.height()), (64, 64));
+    // Row 40 is on the second page, it would wrap to row 8 on a 64x32 display
+    let lit = chip8.framebuffer.iter().position(|&pixel| pixel != 0);
+    assert_eq!(lit, Some(40 * 64 + 40));
+}
+
+#[test]
+fn hires_clear() {
+    let chip8 = run(Chip8Config::chip8_hires(), 5);
+    assert_eq!(chip8.pc(), 0x2C8);
+    assert!(chip8.framebuffer.iter().all(|&pixel| pixel == 0));
+}
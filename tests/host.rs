@@ -0,0 +1,88 @@
+use chip8::{
+    Chip8, Chip8Config, Chip8Error, FlagStorage, Host, Machine, MachineCode, MachineCodeHandler,
+};
+
+#[derive(Default)]
+struct Calls(Vec<u16>);
+
+impl MachineCodeHandler for Calls {
+    fn call(&mut self, address: u16, machine: Machine<'_>) -> Result<(), Chip8Error> {
+        self.0.push(address);
+        machine.variable_reg[0x1] = 0x42;
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Flags(Option<[u8; 16]>);
+
+impl FlagStorage for Flags {
+    fn save(&mut self, flags: &[u8; 16]) -> Result<(), Chip8Error> {
+        self.0 = Some(*flags);
+        Ok(())
+    }
+    fn load(&mut self, flags: &mut [u8; 16]) -> Result<(), Chip8Error> {
+        if let Some(saved) = self.0 {
+            *flags = saved;
+        }
+        Ok(())
+    }
+}
+
+#[rustfmt::skip]
+const PROGRAM: [u8; 6] = [
+    0x01, 0x23, // 0x123 // machine code routine
+    0xF1, 0x75, // saveflags v1
+    0x12, 0x04, // loop
+];
+
+fn chip8() -> Chip8 {
+    let config = Chip8Config {
+        machine_code: MachineCode::Custom,
+        ..Chip8Config::schip_1_1()
+    };
+    let mut chip8 = Chip8::new(config);
+    chip8.set_program(&PROGRAM).unwrap();
+    chip8
+}
+
+#[test]
+fn update_with_handler_and_storage() {
+    let mut chip8 = chip8();
+    let (mut calls, mut flags) = (Calls::default(), Flags::default());
+    let mut host = Host {
+        handler: Some(&mut calls),
+        storage: Some(&mut flags),
+    };
+    // A tenth of a second runs far more than the three instructions
+    chip8.update_with(100_000, || 0, &mut host).unwrap();
+    assert_eq!(calls.0, [0x123]);
+    assert_eq!(flags.0.unwrap()[..2], [0x00, 0x42]);
+}
+
+#[test]
+fn step_with_handler_and_storage() {
+    let mut chip8 = chip8();
+    let (mut calls, mut flags) = (Calls::default(), Flags::default());
+    let mut host = Host {
+        handler: Some(&mut calls),
+        storage: Some(&mut flags),
+    };
+    chip8.step_with(|| 0, &mut host).unwrap();
+    chip8.step_with(|| 0, &mut host).unwrap();
+    assert_eq!(calls.0, [0x123]);
+    assert_eq!(flags.0.unwrap()[..2], [0x00, 0x42]);
+}
+
+#[test]
+fn run_frame_with_handler_and_storage() {
+    let mut chip8 = chip8();
+    let (mut calls, mut flags) = (Calls::default(), Flags::default());
+    let mut host = Host {
+        handler: Some(&mut calls),
+        storage: Some(&mut flags),
+    };
+    chip8.run_frame_with(10, || 0, &mut host).unwrap();
+    assert_eq!(calls.0, [0x123]);
+    assert_eq!(flags.0.unwrap()[..2], [0x00, 0x42]);
+}
@@ -0,0 +1,52 @@
+use chip8::{Instruction, Variant};
+
+const VARIANTS: &[Variant] = &[
+    Variant::Chip8,
+    Variant::Chip8Hires,
+    Variant::SuperChip,
+    Variant::XoChip,
+    Variant::Chip8X,
+    Variant::Chip8E,
+    #[cfg(feature = "alloc")]
+    Variant::MegaChip,
+];
+
+#[test]
+fn every_opcode_round_trips() {
+    for &variant in VARIANTS {
+        for opcode in 0..=u16::MAX {
+            if let Ok(instruction) = Instruction::decode_for(opcode, variant) {
+                assert_eq!(
+                    instruction.encode(),
+                    opcode,
+                    "{instruction:?} on {variant:?}"
+                );
+                assert_eq!(
+                    Instruction::decode_for(instruction.encode(), variant),
+                    Ok(instruction)
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn undefined_5xyn_and_9xyn() {
+    for &variant in VARIANTS {
+        for n in 0x4..=0xF {
+            assert!(Instruction::decode_for(0x5120 | n, variant).is_err());
+            assert!(Instruction::decode_for(0x9120 | n, variant).is_err());
+        }
+        assert!(Instruction::decode_for(0x9121, variant).is_err());
+    }
+    assert!(Instruction::decode_for(0x5121, Variant::Chip8).is_err());
+    assert!(Instruction::decode_for(0x5122, Variant::SuperChip).is_err());
+    assert_eq!(
+        Instruction::decode_for(0x5121, Variant::Chip8E),
+        Ok(Instruction::SkipIfGreater { x: 1, y: 2 })
+    );
+    assert_eq!(
+        Instruction::decode_for(0x5122, Variant::XoChip),
+        Ok(Instruction::SaveRange { x: 1, y: 2 })
+    );
+}
@@ -0,0 +1,41 @@
+use chip8::{Chip8, Chip8Config};
+
+/// Sets v0 and v2, then runs `jump`
+fn jump(config: Chip8Config, jump: [u8; 2]) -> usize {
+    #[rustfmt::skip]
+    let program = [
+        0x60, 0x01, // v0 := 1
+        0x62, 0x10, // v2 := 0x10
+        jump[0], jump[1],
+    ];
+    let mut chip8 = Chip8::new(config);
+    chip8.set_program(&program).unwrap();
+    for _ in 0..3 {
+        chip8.step(|| 0).unwrap();
+    }
+    chip8.pc()
+}
+
+#[test]
+fn jump_with_v0() {
+    // jump0 0x230
+    assert_eq!(jump(Chip8Config::modern(), [0xB2, 0x30]), 0x231);
+}
+
+#[test]
+fn jump_with_vx() {
+    // jump0 0x230, read as jump 0x230 + v2
+    assert_eq!(jump(Chip8Config::schip_1_1(), [0xB2, 0x30]), 0x240);
+}
+
+#[test]
+fn jump_wraps_inside_memory() {
+    // jump0 0xFFF
+    assert_eq!(jump(Chip8Config::modern(), [0xBF, 0xFF]), 0x000);
+    // jump0 0xFFF, read as jump 0xFFF + vF
+    let config = Chip8Config {
+        memory_size: 0x800,
+        ..Chip8Config::schip_1_1()
+    };
+    assert_eq!(jump(config, [0xBF, 0xFF]), 0x7FF);
+}
@@ -0,0 +1,85 @@
+use chip8::{Chip8, Chip8Config};
+
+#[rustfmt::skip]
+const PROGRAM: [u8; 6] = [
+    0xF0, 0x0A, // v0 := key
+    0xF1, 0x0A, // v1 := key
+    0x12, 0x04, // loop
+];
+
+fn chip8(config: Chip8Config) -> Chip8 {
+    let mut chip8 = Chip8::new(config);
+    chip8.set_program(&PROGRAM).unwrap();
+    chip8
+}
+
+#[test]
+fn two_presses_between_steps() {
+    let mut chip8 = chip8(Chip8Config::modern());
+    chip8.press(0x1);
+    chip8.press(0x2);
+    chip8.step(|| 0).unwrap();
+    chip8.step(|| 0).unwrap();
+    assert_eq!(chip8.variable_reg()[..2], [0x1, 0x2]);
+    assert_eq!(chip8.pc(), 0x204);
+}
+
+#[test]
+fn held_key_is_not_pressed_again() {
+    let mut chip8 = chip8(Chip8Config::modern());
+    chip8.press(0x1);
+    chip8.press(0x1);
+    chip8.step(|| 0).unwrap();
+    chip8.step(|| 0).unwrap();
+    assert_eq!(chip8.variable_reg()[0], 0x1);
+    assert_eq!(chip8.pc(), 0x202);
+}
+
+#[test]
+fn wait_for_release_of_the_first_key() {
+    let mut chip8 = chip8(Chip8Config {
+        wait_for_key_release: true,
+        ..Chip8Config::modern()
+    });
+    chip8.press(0x3);
+    chip8.step(|| 0).unwrap();
+    assert_eq!(chip8.pc(), 0x200);
+    chip8.press(0x4);
+    chip8.release(0x4);
+    chip8.step(|| 0).unwrap();
+    assert_eq!(chip8.pc(), 0x200);
+    chip8.release(0x3);
+    chip8.step(|| 0).unwrap();
+    assert_eq!(chip8.variable_reg()[0], 0x3);
+    assert_eq!(chip8.pc(), 0x202);
+}
+
+#[test]
+fn press_before_an_update_that_runs_nothing() {
+    let mut chip8 = chip8(Chip8Config::modern());
+    chip8.press(0x5);
+    // Too short for an instruction
+    chip8.update(1, || 0).unwrap();
+    assert_eq!(chip8.pc(), 0x200);
+    chip8.update(10_000, || 0).unwrap();
+    assert_eq!(chip8.variable_reg()[0], 0x5);
+}
+
+#[test]
+fn events_before_fx0a_are_dropped() {
+    let mut chip8 = Chip8::new(Chip8Config::modern());
+    #[rustfmt::skip]
+    chip8.set_program(&[
+        0x60, 0x00, // v0 := 0
+        0xF0, 0x0A, // v0 := key
+    ]).unwrap();
+    chip8.press(0x6);
+    chip8.step(|| 0).unwrap();
+    chip8.step(|| 0).unwrap();
+    // The key was already down when FX0A started waiting
+    assert_eq!(chip8.pc(), 0x202);
+    chip8.release(0x6);
+    chip8.press(0x6);
+    chip8.step(|| 0).unwrap();
+    assert_eq!(chip8.variable_reg()[0], 0x6);
+}
@@ -0,0 +1,85 @@
+#![cfg(feature = "alloc")]
+
+use chip8::{Chip8, Chip8Config, Chip8Error};
+
+/// Draws a pixel of palette colour 1, runs `setup`, draws colour 2 on top and presents the frame
+fn run(colors: [u32; 2], setup: [u8; 2]) -> Chip8 {
+    #[rustfmt::skip]
+    let mut program = vec![
+        0x00, 0x11, // megaon
+        0x01, 0x00, 0x02, 0x30, // i := long palette
+        0x02, 0x02, // load 2 palette colours
+        0x03, 0x01, // sprite width 1
+        0x04, 0x01, // sprite height 1
+        0xA2, 0x38, // i := sprite
+        0xD0, 0x01, // sprite v0 v0 1
+        setup[0], setup[1],
+        0xA2, 0x39, // i := sprite + 1
+        0xD0, 0x01, // sprite v0 v0 1
+        0x00, 0xE0, // present
+        0x12, 0x1A, // loop
+    ];
+    program.resize(0x30, 0);
+    // palette:
+    program.extend(colors.iter().flat_map(|color| color.to_be_bytes()));
+    // sprite:
+    program.extend([0x01, 0x02]);
+
+    let mut chip8 = Chip8::new(Chip8Config::megachip());
+    chip8.set_program(&program).unwrap();
+    for _ in 0..12 {
+        chip8.step(|| 0).unwrap();
+    }
+    chip8
+}
+
+fn pixel(chip8: &Chip8) -> u32 {
+    chip8.megachip_framebuffer().unwrap()[0]
+}
+
+const RED: u32 = 0xFFFF0000;
+const BLUE: u32 = 0xFF0000FF;
+
+#[test]
+fn blend_modes() {
+    // blend mode N, each mode mixes blue into red differently
+    let blended = |mode: u8| pixel(&run([RED, BLUE], [0x08, mode]));
+    assert_eq!(blended(0), 0xFF0000FF);
+    assert_eq!(blended(1), 0xFFBF003F);
+    assert_eq!(blended(2), 0xFF7F007F);
+    assert_eq!(blended(3), 0xFF3F00BF);
+    assert_eq!(blended(4), 0xFFFF00FF);
+    assert_eq!(blended(5), 0xFF000000);
+}
+
+#[test]
+fn palette_alpha() {
+    // A half transparent blue is faded in over red
+    let chip8 = run([RED, 0x800000FF], [0x08, 0x00]);
+    assert_eq!(pixel(&chip8), 0xFF7F0080);
+}
+
+#[test]
+fn collision_color() {
+    // collision colour 1, drawing over colour 1 collides
+    let chip8 = run([RED, BLUE], [0x09, 0x01]);
+    assert_eq!(chip8.variable_reg()[0xF], 1);
+    // collision colour 2, only drawing over colour 2 would collide
+    let chip8 = run([RED, BLUE], [0x09, 0x02]);
+    assert_eq!(chip8.variable_reg()[0xF], 0);
+}
+
+#[test]
+fn long_index_past_the_end_of_memory() {
+    let mut chip8 = Chip8::new(Chip8Config::megachip());
+    // i := long 0x010000
+    chip8.set_program(&[0x01, 0x01, 0x00, 0x00]).unwrap();
+    let error = chip8.step(|| 0).unwrap_err();
+    assert!(matches!(
+        error,
+        Chip8Error::MemoryOutOfBounds {
+            pc: 0x200,
+            address: 0x10000
+        }
+    ));
+}
@@ -0,0 +1,128 @@
+use chip8::{Chip8, Chip8Config, Chip8Error, MemoryAccess};
+
+fn chip8(memory_access: MemoryAccess) -> Chip8 {
+    Chip8::new(Chip8Config {
+        memory_access,
+        ..Chip8Config::modern()
+    })
+}
+
+#[test]
+fn program_too_large() {
+    let mut chip8 = chip8(MemoryAccess::Wrap);
+    assert!(chip8.set_program(&[0x12; 0xE00]).is_ok());
+    assert!(matches!(
+        chip8.set_program(&[0x12; 0xE01]),
+        Err(Chip8Error::ProgramTooLarge {
+            size: 0xE01,
+            max_size: 0xE00
+        })
+    ));
+}
+
+#[test]
+fn no_memory() {
+    let mut chip8 = Chip8::new(Chip8Config {
+        memory_size: 0,
+        ..Chip8Config::modern()
+    });
+    // Memory still holds the fonts and the start of the program
+    assert_eq!(chip8.memory().len(), 0x201);
+    chip8.set_program(&[0x60]).unwrap();
+    for _ in 0..4 {
+        let _ = chip8.step(|| 0);
+    }
+}
+
+#[test]
+fn fetch_at_the_end_of_memory() {
+    for memory_access in [MemoryAccess::Wrap, MemoryAccess::Error] {
+        let mut chip8 = chip8(memory_access);
+        chip8.memory_mut()[0xFFE..].copy_from_slice(&[0x60, 0x07]); // v0 := 7
+        chip8.memory_mut()[..2].copy_from_slice(&[0x12, 0x00]); // jump 0x200
+        chip8.set_pc(0xFFE);
+        chip8.step(|| 0).unwrap();
+        assert_eq!(chip8.variable_reg()[0], 7);
+
+        let result = chip8.step(|| 0);
+        if memory_access == MemoryAccess::Wrap {
+            assert!(result.is_ok());
+            assert_eq!(chip8.pc(), 0x200);
+        } else {
+            assert!(matches!(
+                result,
+                Err(Chip8Error::MemoryOutOfBounds {
+                    pc: 0x1000,
+                    address: 0x1000
+                })
+            ));
+        }
+    }
+}
+
+#[test]
+fn instruction_across_the_end_of_memory() {
+    let mut chip8 = chip8(MemoryAccess::Error);
+    chip8.memory_mut()[0xFFF] = 0x60;
+    chip8.set_pc(0xFFF);
+    assert!(matches!(
+        chip8.step(|| 0),
+        Err(Chip8Error::MemoryOutOfBounds {
+            pc: 0xFFF,
+            address: 0x1000
+        })
+    ));
+}
+
+#[rustfmt::skip]
+const DRAW: [u8; 6] = [
+    0xAF, 0xFE, // i := 0xFFE
+    0xD0, 0x03, // sprite v0 v0 3
+    0x12, 0x04, // loop
+];
+
+#[test]
+fn draw_wraps_at_the_end_of_memory() {
+    let mut chip8 = chip8(MemoryAccess::Wrap);
+    chip8.set_program(&DRAW).unwrap();
+    chip8.memory_mut()[0xFFE..].copy_from_slice(&[0x80, 0x80]);
+    chip8.memory_mut()[0] = 0x80;
+    chip8.step(|| 0).unwrap();
+    chip8.step(|| 0).unwrap();
+    let width = chip8.width();
+    for row in 0..3 {
+        assert_ne!(chip8.framebuffer[row * width], 0);
+    }
+}
+
+#[test]
+fn draw_past_the_end_of_memory() {
+    let mut chip8 = chip8(MemoryAccess::Error);
+    chip8.set_program(&DRAW).unwrap();
+    chip8.step(|| 0).unwrap();
+    assert!(matches!(
+        chip8.step(|| 0),
+        Err(Chip8Error::MemoryOutOfBounds {
+            pc: 0x202,
+            address: 0x1000
+        })
+    ));
+}
+
+#[test]
+fn load_past_the_end_of_memory() {
+    let mut chip8 = chip8(MemoryAccess::Error);
+    #[rustfmt::skip]
+    chip8.set_program(&[
+        0xAF, 0xFF, // i := 0xFFF
+        0xF1, 0x65, // load v1
+    ]).unwrap();
+    chip8.step(|| 0).unwrap();
+    assert!(matches!(
+        chip8.step(|| 0),
+        Err(Chip8Error::MemoryOutOfBounds {
+            pc: 0x202,
+            address: 0x1000
+        })
+    ));
+}
@@ -0,0 +1,52 @@
+use chip8::{Chip8, Chip8Config};
+
+fn run(config: Chip8Config, program: &[u8], steps: usize) -> Chip8 {
+    let mut chip8 = Chip8::new(config);
+    chip8.set_program(program).unwrap();
+    for _ in 0..steps {
+        chip8.step(|| 0).unwrap();
+        chip8.tick_timers();
+    }
+    chip8
+}
+
+#[test]
+fn eti_660() {
+    // Loaded at 0x600
+    #[rustfmt::skip]
+    let program = [
+        0x60, 0x32, // v0 := 50
+        0xA6, 0x08, // i := sprite
+        0xD0, 0x01, // sprite v0 v0 1
+        0x16, 0x06, // loop
+        0x80, // sprite: one pixel
+    ];
+    let chip8 = run(Chip8Config::eti_660(), &program, 3);
+    assert_eq!(chip8.pc(), 0x606);
+    assert_eq!((chip8.width(), chip8.height()), (64, 48));
+    // The sprite starts on row 50 % 48
+    let lit = chip8.framebuffer.iter().position(|&pixel| pixel != 0);
+    assert_eq!(lit, Some(2 * 64 + 50));
+}
+
+#[test]
+fn dream_6800() {
+    #[rustfmt::skip]
+    let program = [
+        0x6F, 0x01, // vf := 1
+        0x60, 0xF0, // v0 := 0xF0
+        0x61, 0x0F, // v1 := 0x0F
+        0x80, 0x11, // v0 |= v1
+        0x22, 0x0C, // sub
+        0x12, 0x0A, // loop
+        // sub:
+        0x12, 0x0C, // loop
+    ];
+    let chip8 = run(Chip8Config::dream_6800(), &program, 6);
+    // The logic instructions reset vf like the COSMAC VIP's
+    assert_eq!(chip8.variable_reg()[0x0], 0xFF);
+    assert_eq!(chip8.variable_reg()[0xF], 0);
+    assert_eq!(chip8.state().stack[0], 0x20A);
+    // The return address isn't written to memory
+    assert!(chip8.memory().iter().skip(0x20E).all(|&byte| byte == 0));
+}
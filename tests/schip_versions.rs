@@ -0,0 +1,58 @@
+use chip8::{Chip8, Chip8Config};
+
+/// Runs `program` in low resolution, one `step` per instruction
+fn run(config: Chip8Config, program: &[u8]) -> Chip8 {
+    let mut chip8 = Chip8::new(config);
+    chip8.set_program(program).unwrap();
+    for _ in 0..program.len() / 2 {
+        chip8.step(|| 0).unwrap();
+    }
+    chip8
+}
+
+fn lit_pixels(chip8: &Chip8) -> usize {
+    let pixels = chip8.width() * chip8.height();
+    chip8.framebuffer[..pixels]
+        .iter()
+        .filter(|&&pixel| pixel != 0)
+        .count()
+}
+
+#[test]
+fn big_sprite_in_lores() {
+    #[rustfmt::skip]
+    let program = [
+        0xA2, 0x06, // i := sprite
+        0xD0, 0x00, // sprite v0 v0 0
+        0x12, 0x04, // loop
+        // sprite: 32 bytes of 0xFF
+    ];
+    let mut program = program.to_vec();
+    program.extend([0xFF; 32]);
+    // 1.0 draws 8x16, 1.1 draws 16x16
+    assert_eq!(lit_pixels(&run(Chip8Config::schip_1_0(), &program)), 8 * 16);
+    assert_eq!(
+        lit_pixels(&run(Chip8Config::schip_1_1(), &program)),
+        16 * 16
+    );
+}
+
+#[test]
+fn scroll_in_lores() {
+    #[rustfmt::skip]
+    let program = [
+        0xA2, 0x08, // i := sprite
+        0xD0, 0x01, // sprite v0 v0 1
+        0x00, 0xC4, // scroll-down 4
+        0x00, 0xFB, // scroll-right
+        0x80, 0x00, // sprite: one pixel
+    ];
+    let lit = |chip8: &Chip8| {
+        let width = chip8.width();
+        let index = chip8.framebuffer.iter().position(|&pixel| pixel != 0);
+        index.map(|index| (index % width, index / width))
+    };
+    // 1.1 scrolls the calculator's 128x64 screen, so lores moves half as far
+    assert_eq!(lit(&run(Chip8Config::schip_1_1(), &program)), Some((2, 2)));
+    assert_eq!(lit(&run(Chip8Config::xo_chip(), &program)), Some((4, 4)));
+}
@@ -0,0 +1,51 @@
+use chip8::{Chip8, Chip8Config};
+
+/// Draws a row of 8 pixels at (8, 8) in high resolution, then runs `scroll` and returns the lit pixels
+fn scroll(config: Chip8Config, scroll: [u8; 2]) -> Vec<(usize, usize)> {
+    #[rustfmt::skip]
+    let program = [
+        0x00, 0xFF, // hires
+        0x60, 0x08, // v0 := 8
+        0xA2, 0x0C, // i := sprite
+        0xD0, 0x01, // sprite v0 v0 1
+        scroll[0], scroll[1],
+        0x12, 0x0A, // loop
+        0xFF, // sprite
+    ];
+    let mut chip8 = Chip8::new(config);
+    chip8.set_program(&program).unwrap();
+    for _ in 0..5 {
+        chip8.step(|| 0).unwrap();
+    }
+    let width = chip8.width();
+    let pixels = width * chip8.height();
+    (0..pixels)
+        .filter(|&i| chip8.framebuffer[i] != 0)
+        .map(|i| (i % width, i / width))
+        .collect()
+}
+
+fn row(x: usize, y: usize) -> Vec<(usize, usize)> {
+    (x..x + 8).map(|x| (x, y)).collect()
+}
+
+#[test]
+fn scroll_right() {
+    // The row overlaps itself, so every pixel has to move before it's overwritten
+    assert_eq!(scroll(Chip8Config::schip_1_1(), [0x00, 0xFB]), row(12, 8));
+}
+
+#[test]
+fn scroll_left() {
+    assert_eq!(scroll(Chip8Config::schip_1_1(), [0x00, 0xFC]), row(4, 8));
+}
+
+#[test]
+fn scroll_down() {
+    assert_eq!(scroll(Chip8Config::schip_1_1(), [0x00, 0xC3]), row(8, 11));
+}
+
+#[test]
+fn scroll_up() {
+    assert_eq!(scroll(Chip8Config::xo_chip(), [0x00, 0xD3]), row(8, 5));
+}
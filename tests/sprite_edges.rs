@@ -0,0 +1,115 @@
+use chip8::{Chip8, Chip8Config};
+
+/// Long enough for exactly one instruction per `update` with the default config
+const STEP: u128 = 1_000_000 / Chip8Config::INSTRUCTIONS_PER_SECOND as u128 + 1;
+
+/// Draws a 2x2 block of pixels (the left two columns of an 8x2 sprite) at `(x, y)`
+fn draw(config: Chip8Config, x: u8, y: u8) -> Chip8 {
+    #[rustfmt::skip]
+    let program = [
+        0x6A, x,    // vA := x
+        0x6B, y,    // vB := y
+        0xA2, 0x0A, // i := sprite
+        0xDA, 0xB2, // sprite vA vB 2
+        0x12, 0x08, // loop
+        0xC0, 0xC0, // sprite
+    ];
+    let mut chip8 = Chip8::new(config);
+    chip8.set_program(&program).unwrap();
+    for _ in 0..5 {
+        chip8.update(STEP, || 0).unwrap();
+    }
+    chip8
+}
+
+fn lit_pixels(chip8: &Chip8) -> Vec<(usize, usize)> {
+    let mut pixels = Vec::new();
+    for y in 0..Chip8::HEIGHT {
+        for x in 0..Chip8::WIDTH {
+            if chip8.framebuffer[y * Chip8::WIDTH + x] != 0 {
+                pixels.push((x, y));
+            }
+        }
+    }
+    pixels
+}
+
+fn clip() -> Chip8Config {
+    Chip8Config {
+        wrap_sprites: false,
+        ..Default::default()
+    }
+}
+
+fn wrap() -> Chip8Config {
+    Chip8Config {
+        wrap_sprites: true,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn inside_the_screen() {
+    let expected = vec![(10, 10), (11, 10), (10, 11), (11, 11)];
+    assert_eq!(lit_pixels(&draw(clip(), 10, 10)), expected);
+    assert_eq!(lit_pixels(&draw(wrap(), 10, 10)), expected);
+}
+
+#[test]
+fn start_coordinate_always_wraps() {
+    let expected = vec![(2, 3), (3, 3), (2, 4), (3, 4)];
+    assert_eq!(lit_pixels(&draw(clip(), 66, 35)), expected);
+    assert_eq!(lit_pixels(&draw(wrap(), 66, 35)), expected);
+}
+
+#[test]
+fn right_edge() {
+    assert_eq!(lit_pixels(&draw(clip(), 63, 10)), vec![(63, 10), (63, 11)]);
+    assert_eq!(
+        lit_pixels(&draw(wrap(), 63, 10)),
+        vec![(0, 10), (63, 10), (0, 11), (63, 11)]
+    );
+}
+
+#[test]
+fn bottom_edge() {
+    assert_eq!(lit_pixels(&draw(clip(), 10, 31)), vec![(10, 31), (11, 31)]);
+    assert_eq!(
+        lit_pixels(&draw(wrap(), 10, 31)),
+        vec![(10, 0), (11, 0), (10, 31), (11, 31)]
+    );
+}
+
+#[test]
+fn bottom_right_corner() {
+    assert_eq!(lit_pixels(&draw(clip(), 63, 31)), vec![(63, 31)]);
+    // Wrapping touches every corner of the screen
+    assert_eq!(
+        lit_pixels(&draw(wrap(), 63, 31)),
+        vec![(0, 0), (63, 0), (0, 31), (63, 31)]
+    );
+}
+
+#[test]
+fn wrapped_pixels_collide() {
+    #[rustfmt::skip]
+    let program = [
+        0x6A, 0x3F, // vA := 63
+        0x6B, 0x1F, // vB := 31
+        0xA2, 0x14, // i := sprite
+        0xDA, 0xB2, // sprite vA vB 2
+        0x6A, 0x00, // vA := 0
+        0x6B, 0x00, // vB := 0
+        0xDA, 0xB1, // sprite vA vB 1 // Erases (0, 0) and (1, 0)
+        0x3F, 0x01, // if vf != 1 then
+        0x00, 0xE0, // clear
+        0x12, 0x12, // loop
+        0xC0, 0xC0, // sprite
+    ];
+    let mut chip8 = Chip8::new(wrap());
+    chip8.set_program(&program).unwrap();
+    for _ in 0..10 {
+        chip8.update(STEP, || 0).unwrap();
+    }
+    assert_eq!(lit_pixels(&chip8), vec![(1, 0), (63, 0), (0, 31), (63, 31)]);
+}
@@ -0,0 +1,57 @@
+use chip8::{Chip8, Chip8Config, Chip8Error};
+
+#[test]
+fn cosmac_vip_stack_grows_down_from_0xecf() {
+    #[rustfmt::skip]
+    let program = [
+        0x22, 0x04, // a
+        0x12, 0x02, // loop
+        // a:
+        0x22, 0x08, // b
+        0x00, 0xEE, // return
+        // b:
+        0xAE, 0xCC, // i := 0xECC
+        0xF3, 0x65, // load v3 // Both return addresses, the innermost one first
+        0x00, 0xEE, // return
+    ];
+    let mut chip8 = Chip8::new(Chip8Config::cosmac_vip());
+    chip8.set_program(&program).unwrap();
+    for _ in 0..4 {
+        chip8.step(|| 0).unwrap();
+    }
+    assert_eq!(chip8.variable_reg()[..4], [0x02, 0x06, 0x02, 0x02]);
+    assert_eq!(chip8.state().stack[..2], [0x202, 0x206]);
+
+    // Both returns pop what the program sees in memory
+    chip8.memory_mut()[0xECD] = 0x02;
+    chip8.step(|| 0).unwrap();
+    assert_eq!(chip8.pc(), 0x202);
+}
+
+#[test]
+fn stack_outside_memory() {
+    let mut chip8 = Chip8::new(Chip8Config::modern());
+    chip8
+        .set_program(&[0x22, 0x04, 0x12, 0x02, 0x00, 0xEE])
+        .unwrap();
+    chip8.step(|| 0).unwrap();
+    assert_eq!(chip8.state().stack[0], 0x202);
+    assert!(chip8.memory().iter().skip(0x206).all(|&byte| byte == 0));
+}
+
+#[test]
+fn recursion_overflows_at_stack_depth() {
+    for config in [Chip8Config::modern(), Chip8Config::cosmac_vip()] {
+        let stack_depth = config.stack_depth;
+        let mut chip8 = Chip8::new(config);
+        chip8.set_program(&[0x22, 0x00]).unwrap(); // Calls itself
+        for _ in 0..stack_depth {
+            chip8.step(|| 0).unwrap();
+        }
+        assert!(matches!(
+            chip8.step(|| 0),
+            Err(Chip8Error::StackOverflow { pc: 0x200, .. })
+        ));
+        assert_eq!(chip8.state().stack_len, stack_depth);
+    }
+}
@@ -0,0 +1,190 @@
+use chip8::{Chip8, Chip8Config, Chip8Error, MemoryAccess};
+
+/// Runs `steps` instructions of `program` and a few frames of timers
+fn run(config: Chip8Config, program: &[u8], steps: usize) -> Chip8 {
+    let mut chip8 = Chip8::new(config);
+    chip8.set_program(program).unwrap();
+    for _ in 0..steps {
+        chip8.step(|| 0x5A).unwrap();
+    }
+    // Part of a frame, so the timers have time left over
+    chip8.update(20_000, || 0x5A).unwrap();
+    chip8
+}
+
+fn save(chip8: &Chip8) -> Vec<u8> {
+    let mut buf = vec![0; chip8.state_size()];
+    assert_eq!(chip8.save_state(&mut buf).unwrap(), buf.len());
+    buf
+}
+
+#[rustfmt::skip]
+const PROGRAM: [u8; 20] = [
+    0x60, 0x12, // v0 := 0x12
+    0x6E, 0xFF, // vE := 0xFF
+    0xF0, 0x15, // delay := v0
+    0xF0, 0x18, // buzzer := v0
+    0xA3, 0x00, // i := 0x300
+    0xFE, 0x33, // bcd vE
+    0x00, 0xFF, // hires
+    0xD0, 0x05, // sprite v0 v0 5
+    0x22, 0x14, // sub
+    0x12, 0x12, // loop
+    // sub: jumps to itself, the return address stays on the stack
+];
+
+fn program() -> Vec<u8> {
+    let mut program = PROGRAM.to_vec();
+    program.extend([0x12, 0x14]);
+    program
+}
+
+#[test]
+fn round_trip() {
+    // Quirks that differ from the defaults, so a lost quirk would show up
+    let config = Chip8Config {
+        half_scroll_in_lores: false,
+        jump_with_vx: false,
+        ..Chip8Config::schip_1_1()
+    };
+    let mut chip8 = run(config, &program(), 9);
+    let state = save(&chip8);
+    let mut loaded = Chip8::load_state(&state).unwrap();
+
+    assert_eq!(loaded.state(), chip8.state());
+    assert_eq!(loaded.memory(), chip8.memory());
+    assert_eq!(loaded.memory()[0x300..0x303], [2, 5, 5]);
+    assert_eq!(loaded.state().stack_len, 1);
+    assert!(loaded.delay_timer() > 0 && loaded.delay_timer() < 0x12);
+    assert_eq!(loaded.framebuffer, chip8.framebuffer);
+    // Config, quirks and everything else that is saved comes back the same
+    assert_eq!(save(&loaded), state);
+
+    // Both carry on the same, including the time left until the next tick
+    for _ in 0..3 {
+        chip8.update(7_000, || 0).unwrap();
+        loaded.update(7_000, || 0).unwrap();
+        assert_eq!(loaded.state(), chip8.state());
+    }
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn megachip_round_trip() {
+    #[rustfmt::skip]
+    let program = [
+        0x00, 0x11, // megaon
+        0x01, 0x00, 0x02, 0x12, // i := long palette
+        0x02, 0x01, // load 1 palette colour
+        0x03, 0x01, // sprite width 1
+        0x04, 0x01, // sprite height 1
+        0xA2, 0x16, // i := sprite
+        0x08, 0x02, // blend mode 2
+        0xD0, 0x01, // sprite v0 v0 1
+        0x12, 0x10, // loop
+        // palette:
+        0xFF, 0x12, 0x34, 0x56,
+        // sprite:
+        0x01,
+    ];
+    let chip8 = run(Chip8Config::megachip(), &program, 9);
+    let state = save(&chip8);
+    let loaded = Chip8::load_state(&state).unwrap();
+    assert!(loaded.is_megachip());
+    assert_eq!(save(&loaded), state);
+}
+
+#[test]
+fn truncated() {
+    let state = save(&run(Chip8Config::schip_1_1(), &program(), 9));
+    for len in (0..state.len()).step_by(7) {
+        assert!(Chip8::load_state(&state[..len]).is_err());
+    }
+    assert!(Chip8::load_state(&state[..state.len() - 1]).is_err());
+}
+
+#[test]
+fn bad_magic_and_version() {
+    let mut state = save(&Chip8::new(Chip8Config::modern()));
+    state[4] = 99;
+    assert!(matches!(
+        Chip8::load_state(&state),
+        Err(Chip8Error::UnsupportedStateVersion { version: 99 })
+    ));
+    state[0] = b'X';
+    assert!(matches!(
+        Chip8::load_state(&state),
+        Err(Chip8Error::InvalidState)
+    ));
+}
+
+// Offsets into a save state of `Chip8Config::modern()`, after the magic, the version, the variant,
+// instructions_per_second and max_catch_up
+const MEMORY_SIZE: usize = 14;
+const DISPLAY_HEIGHT: usize = MEMORY_SIZE + 4;
+const PROGRAM_START: usize = DISPLAY_HEIGHT + 4;
+const STACK_DEPTH: usize = PROGRAM_START + 4 + 80 + 4 + 160 + 4;
+const CONFIG_END: usize = STACK_DEPTH + 4 + 1 + 4 + 1 + 1 + 2;
+/// With an empty stack
+const DS_TIMER: usize = CONFIG_END + 4096 + 4 + 2 + 16 + 4 + 2;
+
+fn crafted(offset: usize, value: &[u8]) -> Result<Chip8, Chip8Error> {
+    let mut state = save(&Chip8::new(Chip8Config::modern()));
+    state[offset..offset + value.len()].copy_from_slice(value);
+    Chip8::load_state(&state)
+}
+
+#[test]
+fn crafted_config() {
+    assert!(crafted(MEMORY_SIZE, &[0, 0, 0x10, 0x00]).is_ok());
+    for memory_size in [0, 0x100, 0x200] {
+        let memory_size = (memory_size as u32).to_be_bytes();
+        assert!(matches!(
+            crafted(MEMORY_SIZE, &memory_size),
+            Err(Chip8Error::InvalidState)
+        ));
+    }
+    assert!(crafted(DISPLAY_HEIGHT, &[0, 0, 0, 0]).is_err());
+    assert!(crafted(PROGRAM_START, &[0, 0, 0x10, 0x00]).is_err());
+    assert!(crafted(STACK_DEPTH, &[0, 0, 0xFF, 0xFF]).is_err());
+}
+
+#[test]
+fn crafted_timer() {
+    assert!(crafted(DS_TIMER, &999_999u64.to_be_bytes()).is_ok());
+    // A whole tick would already have been taken
+    assert!(crafted(DS_TIMER, &1_000_000u64.to_be_bytes()).is_err());
+    assert!(crafted(DS_TIMER, &u64::MAX.to_be_bytes()).is_err());
+}
+
+#[test]
+fn crafted_pc() {
+    let pc = CONFIG_END + 4096;
+    assert!(crafted(pc, &0x1100u32.to_be_bytes()).is_ok());
+    assert!(crafted(pc, &0x1101u32.to_be_bytes()).is_err());
+    assert!(crafted(pc, &u32::MAX.to_be_bytes()).is_err());
+}
+
+#[test]
+fn error_during_update() {
+    let mut chip8 = Chip8::new(Chip8Config::modern());
+    // Not an instruction, so the rest of the frame's instructions are never run
+    chip8.set_program(&[0x80, 0x0F]).unwrap();
+    assert!(chip8.update(16_000, || 0).is_err());
+    let loaded = Chip8::load_state(&save(&chip8)).unwrap();
+    assert_eq!(loaded.state(), chip8.state());
+}
+
+#[test]
+fn error_past_the_end_of_memory() {
+    let mut chip8 = Chip8::new(Chip8Config {
+        memory_access: MemoryAccess::Error,
+        ..Chip8Config::modern()
+    });
+    chip8.set_pc(0xFFF);
+    for _ in 0..200 {
+        assert!(chip8.step(|| 0).is_err());
+    }
+    assert_eq!(chip8.pc(), 0xFFF);
+    assert!(Chip8::load_state(&save(&chip8)).is_ok());
+}
@@ -0,0 +1,43 @@
+use chip8::{Chip8, Chip8Config, Timer};
+
+#[test]
+fn timer_length_carries_the_remainder() {
+    let mut timer = Timer::new(1_000);
+    assert!(!timer.check(999));
+    assert!(timer.check(1));
+    // Two and a half ticks, one is taken now and the rest stays due
+    assert!(timer.check(2_500));
+    assert!(timer.tick());
+    assert!(!timer.tick());
+    assert_eq!(timer.until_tick(), 500);
+}
+
+#[test]
+fn timer_frequency_is_exact() {
+    // 700 Hz isn't a whole number of microseconds
+    let mut timer = Timer::with_frequency(700);
+    let mut ticks = 0;
+    for _ in 0..1_000 {
+        if timer.check(1_000) {
+            ticks += 1;
+        }
+    }
+    assert_eq!(ticks, 700);
+}
+
+#[test]
+fn update_is_capped_at_max_catch_up() {
+    #[rustfmt::skip]
+    let program = [
+        0x60, 0xFF, // v0 := 255
+        0xF0, 0x15, // delay := v0
+        0x12, 0x04, // loop
+    ];
+    let mut chip8 = Chip8::new(Chip8Config::modern());
+    chip8.set_program(&program).unwrap();
+    chip8.step(|| 0).unwrap();
+    chip8.step(|| 0).unwrap();
+    // A quarter of a second is 15 frames
+    chip8.update(u128::MAX, || 0).unwrap();
+    assert_eq!(chip8.delay_timer(), 255 - 15);
+}
@@ -0,0 +1,200 @@
+use chip8::CosmacVip;
+
+/// Puts `program` at 0x8100 in the monitor ROM. The reset vector jumps there, which moves the ROM away from 0x0000
+/// like the real monitor does, so the program can read RAM. Addresses in the comments are the low byte.
+fn vip(program: &[u8]) -> CosmacVip {
+    let mut rom = [0; CosmacVip::ROM_SIZE];
+    // LBR 8100
+    rom[..3].copy_from_slice(&[0xC0, 0x81, 0x00]);
+    rom[0x100..0x100 + program.len()].copy_from_slice(program);
+    CosmacVip::new(&rom, &[0; CosmacVip::INTERPRETER_SIZE])
+}
+
+/// Runs `program` for 10 ms and returns the bytes it stored from 0xF00 on
+fn results(program: &[u8], len: usize) -> Vec<u8> {
+    let mut vip = vip(program);
+    vip.update(10_000);
+    vip.memory()[0xF00..0xF00 + len].to_vec()
+}
+
+#[test]
+fn short_branches() {
+    #[rustfmt::skip]
+    let program = [
+        0xF8, 0x0F, 0xB3, // 00: LDI 0F, PHI R3
+        0xF8, 0x00, 0xA3, // 03: LDI 00, PLO R3 // Results go to 0xF00
+        0xF8, 0x00,       // 06: LDI 00
+        0x32, 0x0E,       // 08: BZ 0E
+        0xF8, 0xEE,       // 0A: LDI EE
+        0x30, 0x10,       // 0C: BR 10
+        0xF8, 0x01,       // 0E: LDI 01
+        0x53, 0x13,       // 10: STR R3, INC R3
+        0x32, 0x18,       // 12: BZ 18 // Not taken, D is 1
+        0xF8, 0x02,       // 14: LDI 02
+        0x30, 0x1A,       // 16: BR 1A
+        0xF8, 0xEE,       // 18: LDI EE
+        0x53, 0x13,       // 1A: STR R3, INC R3
+        0x00,             // 1C: IDL
+    ];
+    assert_eq!(results(&program, 2), [0x01, 0x02]);
+}
+
+#[test]
+fn long_branches_and_skips() {
+    #[rustfmt::skip]
+    let program = [
+        0xF8, 0x0F, 0xB3, // 00: LDI 0F, PHI R3
+        0xF8, 0x00, 0xA3, // 03: LDI 00, PLO R3
+        0xF8, 0x00,       // 06: LDI 00
+        0xC6,             // 08: LSNZ // Not taken, D is 0
+        0xF8, 0x01,       // 09: LDI 01
+        0x53, 0x13,       // 0B: STR R3, INC R3
+        0xC6,             // 0D: LSNZ // Skips the next two bytes
+        0xF8, 0xEE,       // 0E: LDI EE
+        0xF8, 0x02,       // 10: LDI 02
+        0x53, 0x13,       // 12: STR R3, INC R3
+        0xC0, 0x81, 0x1A, // 14: LBR 811A
+        0xF8, 0xEE,       // 17: LDI EE
+        0x00,             // 19: IDL
+        0xF8, 0x03,       // 1A: LDI 03
+        0x53, 0x13,       // 1C: STR R3, INC R3
+        0x00,             // 1E: IDL
+    ];
+    assert_eq!(results(&program, 3), [0x01, 0x02, 0x03]);
+}
+
+#[test]
+fn carry_and_borrow() {
+    #[rustfmt::skip]
+    let program = [
+        0xF8, 0x0F, 0xB3, // 00: LDI 0F, PHI R3
+        0xF8, 0x00, 0xA3, // 03: LDI 00, PLO R3
+        0xF8, 0xFF,       // 06: LDI FF
+        0xFC, 0x02,       // 08: ADI 02 // Carries
+        0x53, 0x13,       // 0A: STR R3, INC R3
+        0xF8, 0x00,       // 0C: LDI 00
+        0x7C, 0x00,       // 0E: ADCI 00 // D = DF
+        0x53, 0x13,       // 10: STR R3, INC R3
+        0xF8, 0x05,       // 12: LDI 05
+        0xFF, 0x07,       // 14: SMI 07 // Borrows, DF is cleared
+        0x53, 0x13,       // 16: STR R3, INC R3
+        0xF8, 0x00,       // 18: LDI 00
+        0x7C, 0x00,       // 1A: ADCI 00
+        0x53, 0x13,       // 1C: STR R3, INC R3
+        0xF8, 0x07,       // 1E: LDI 07
+        0xFD, 0x09,       // 20: SDI 09 // No borrow, DF is set
+        0x53, 0x13,       // 22: STR R3, INC R3
+        0xF8, 0x00,       // 24: LDI 00
+        0x7C, 0x00,       // 26: ADCI 00
+        0x53, 0x13,       // 28: STR R3, INC R3
+        0x00,             // 2A: IDL
+    ];
+    assert_eq!(results(&program, 6), [0x01, 0x01, 0xFE, 0x00, 0x02, 0x01]);
+}
+
+#[test]
+fn mark_and_return() {
+    #[rustfmt::skip]
+    let program = [
+        0xF8, 0x0F, 0xB5, // 00: LDI 0F, PHI R5
+        0xF8, 0x20, 0xA5, // 03: LDI 20, PLO R5
+        0xF8, 0x0F, 0xB2, // 06: LDI 0F, PHI R2
+        0xF8, 0x10, 0xA2, // 09: LDI 10, PLO R2
+        0xE5,             // 0C: SEX 5
+        0x79,             // 0D: MARK // Saves X = 5, P = 0 at 0xF10, X = P
+        0x12,             // 0E: INC R2
+        0xE2,             // 0F: SEX 2
+        0x70,             // 10: RET // Restores X = 5, P = 0
+        0xF8, 0xAB,       // 11: LDI AB
+        0x73,             // 13: STXD // Stores through R5
+        0x00,             // 14: IDL
+    ];
+    let mut vip = vip(&program);
+    vip.update(10_000);
+    assert_eq!(vip.memory()[0xF10], 0x50);
+    assert_eq!(vip.memory()[0xF20], 0xAB);
+}
+
+#[test]
+fn interrupt_entry() {
+    #[rustfmt::skip]
+    let mut program = vec![
+        0xF8, 0x0F, 0xB2, // 00: LDI 0F, PHI R2
+        0xF8, 0x10, 0xA2, // 03: LDI 10, PLO R2
+        0xF8, 0x81, 0xB1, // 06: LDI 81, PHI R1
+        0xF8, 0x20, 0xA1, // 09: LDI 20, PLO R1 // Interrupt handler
+        0xE3,             // 0C: SEX 3
+        0x69,             // 0D: INP 1 // Display on
+        0x30, 0x0E,       // 0E: BR 0E
+    ];
+    program.resize(0x20, 0);
+    #[rustfmt::skip]
+    program.extend([
+        0x78,             // 20: SAV // Stores T = X, P from before the interrupt
+        0x7B,             // 21: SEQ
+        0x00,             // 22: IDL
+    ]);
+    let mut vip = vip(&program);
+    // The CDP1861 interrupts two lines before the display starts at line 80, line 78 is 1092 machine cycles in
+    vip.update(4_900);
+    assert!(!vip.should_play_sound());
+    vip.update(100);
+    assert!(vip.should_play_sound());
+    assert_eq!(vip.memory()[0xF10], 0x30);
+}
+
+#[test]
+fn dma_line_timing() {
+    #[rustfmt::skip]
+    let mut program = vec![
+        0xF8, 0x81, 0xB3, // 00: LDI 81, PHI R3
+        0xF8, 0x08, 0xA3, // 03: LDI 08, PLO R3
+        0x71, 0x23,       // 06: DIS // X = 2, P = 3, interrupts off
+        0xF8, 0x81, 0xB0, // 08: LDI 81, PHI R0
+        0xF8, 0x40, 0xA0, // 0B: LDI 40, PLO R0 // Display data
+        0x69,             // 0E: INP 1 // Display on
+        0x30, 0x0F,       // 0F: BR 0F
+    ];
+    program.resize(0x40, 0);
+    program.extend([0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00]);
+    program.extend([0x80; 8]);
+    let row = |vip: &CosmacVip, row: usize| {
+        let start = row * CosmacVip::WIDTH;
+        vip.framebuffer[start..start + CosmacVip::WIDTH].to_vec()
+    };
+    let first_row: Vec<u8> = (0..64).map(|x| (x / 8 % 2 == 0) as u8).collect();
+    let second_row: Vec<u8> = (0..64).map(|x| (x % 8 == 0) as u8).collect();
+
+    let mut vip = vip(&program);
+    // Line 80 starts 1120 machine cycles in
+    vip.update(5_000);
+    assert!(vip.framebuffer.iter().all(|&pixel| pixel == 0));
+    // Its 8 bytes are fetched 2 cycles into the line, line 81 starts 14 cycles after it
+    vip.update(150);
+    assert_eq!(row(&vip, 0), first_row);
+    assert!(row(&vip, 1).iter().all(|&pixel| pixel == 0));
+    vip.update(100);
+    assert_eq!(row(&vip, 1), second_row);
+}
+
+#[test]
+fn catch_up_is_capped() {
+    #[rustfmt::skip]
+    let program = [
+        0xF8, 0x0F, 0xB3, // 00: LDI 0F, PHI R3
+        0xF8, 0x00, 0xA3, // 03: LDI 00, PLO R3
+        0x14,             // 06: INC R4
+        0x94,             // 07: GHI R4
+        0x53,             // 08: STR R3 // Counts every 256 loops of 8 machine cycles at 0xF00
+        0x30, 0x06,       // 09: BR 06
+    ];
+    let count = |delta: u128| {
+        let mut vip = vip(&program);
+        vip.update(delta);
+        vip.memory()[0xF00]
+    };
+    assert_eq!(count(200_000), 21);
+    assert_eq!(count(250_000), 26);
+    assert_eq!(count(10_000_000), 26);
+    assert_eq!(count(u128::MAX), 26);
+}